@@ -0,0 +1,18 @@
+use puyoai_core::decision::Decision;
+use puyoai_core::field::CoreField;
+use puyoai_core::kumipuyo::Kumipuyo;
+use puyoai_data::GameResult;
+
+use player_state::PlayerState;
+
+pub trait AI {
+    /// Called when a new game begins.
+    fn game_will_begin(&mut self) {}
+
+    /// Called when the game has ended. `result` is from the viewpoint of this AI.
+    fn game_has_ended(&mut self, _result: GameResult) {}
+
+    /// Returns the decision for `seq[0]` on `field`.
+    /// `seq` contains the kumipuyos that are visible now.
+    fn think(&mut self, frame_id: i32, field: &CoreField, seq: &[Kumipuyo], enemy_state: &PlayerState) -> Decision;
+}
@@ -0,0 +1,237 @@
+use std::io::{self, BufRead, Write};
+
+use puyoai_core::color_map::ColorMap;
+use puyoai_core::decision::Decision;
+use puyoai_core::field::CoreField;
+use puyoai_data::{FrameRequest, FrameResponse};
+
+use ai::AI;
+use player_state::PlayerState;
+
+/// Client reads FrameRequests from stdin, and writes FrameResponses to stdout.
+///
+/// On pre_decision_request, the AI thinks the next kumipuyo in advance on the
+/// current field. The result is used on decision_request if the field has not
+/// been changed by a rensa or ojama; otherwise the AI thinks again.
+///
+/// Every request is answered, since a server in lockstep waits for the answer
+/// before the next frame. The answer has only ID unless a decision is requested.
+///
+/// The server sends RealColor, which are mapped to PuyoColor by first appearance in each game.
+/// Since CoreField has only 4 colors, the 5th color in a game is an error.
+pub struct Client<A: AI> {
+    ai: A,
+    playing: bool,
+    color_map: ColorMap,
+    states: [PlayerState; 2],
+    // The field and the decision thought on pre_decision_request.
+    pre_decision: Option<(CoreField, Decision)>,
+}
+
+impl<A: AI> Client<A> {
+    pub fn new(ai: A) -> Client<A> {
+        Client {
+            ai: ai,
+            playing: false,
+            color_map: ColorMap::new(),
+            states: [PlayerState::new(), PlayerState::new()],
+            pre_decision: None,
+        }
+    }
+
+    pub fn ai(&self) -> &A {
+        &self.ai
+    }
+
+    pub fn my_state(&self) -> &PlayerState {
+        &self.states[0]
+    }
+
+    pub fn enemy_state(&self) -> &PlayerState {
+        &self.states[1]
+    }
+
+    /// Runs until stdin is closed.
+    pub fn run(&mut self) -> Result<(), String> {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        for line in stdin.lock().lines() {
+            let line = try!(line.map_err(|e| format!("failed to read stdin: {}", e)));
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let req = try!(FrameRequest::parse(&line));
+            if let Some(resp) = try!(self.handle_frame_request(&req)) {
+                try!(writeln!(out, "{}", resp.to_string())
+                     .and_then(|_| out.flush())
+                     .map_err(|e| format!("failed to write stdout: {}", e)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the states with `req`, and returns the response to it. None is returned when
+    /// the game has finished, since the server doesn't wait for it.
+    /// An error is returned when a color in `req` cannot be mapped to PuyoColor.
+    pub fn handle_frame_request(&mut self, req: &FrameRequest) -> Result<Option<FrameResponse>, String> {
+        if !self.playing {
+            self.playing = true;
+            self.color_map.clear();
+            self.states = [PlayerState::new(), PlayerState::new()];
+            self.pre_decision = None;
+            self.ai.game_will_begin();
+        }
+
+        for i in 0..2 {
+            try!(self.states[i].update(req.frame_id, &req.player_frame_request[i], &mut self.color_map));
+        }
+
+        if req.game_result.is_finished() {
+            self.playing = false;
+            self.ai.game_has_ended(req.game_result);
+            return Ok(None);
+        }
+
+        let event = req.player_frame_request[0].event;
+        if event.pre_decision_request && self.states[0].seq.len() >= 2 {
+            let field = self.states[0].field.clone();
+            let decision = self.ai.think(req.frame_id, &field, &self.states[0].seq[1..], &self.states[1]);
+            self.pre_decision = Some((field, decision));
+        }
+
+        let mut resp = FrameResponse::new(req.frame_id);
+        if !event.decision_request && !event.decision_request_again {
+            return Ok(Some(resp));
+        }
+
+        let decision = match self.pre_decision.take() {
+            Some((ref field, ref decision)) if !event.decision_request_again && *field == self.states[0].field => {
+                decision.clone()
+            },
+            _ => self.ai.think(req.frame_id, &self.states[0].field, &self.states[0].seq, &self.states[1]),
+        };
+
+        resp.decision = decision;
+        Ok(Some(resp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Client;
+    use ai::AI;
+    use player_state::PlayerState;
+    use puyoai_core::color::PuyoColor;
+    use puyoai_core::decision::Decision;
+    use puyoai_core::field::{CoreField, PuyoPlainField};
+    use puyoai_core::kumipuyo::Kumipuyo;
+    use puyoai_data::{FrameRequest, GameResult};
+
+    struct CountingAI {
+        num_games: usize,
+        num_thinks: usize,
+        last_result: Option<GameResult>,
+    }
+
+    impl AI for CountingAI {
+        fn game_will_begin(&mut self) {
+            self.num_games += 1;
+        }
+
+        fn game_has_ended(&mut self, result: GameResult) {
+            self.last_result = Some(result);
+        }
+
+        fn think(&mut self, _frame_id: i32, _field: &CoreField, seq: &[Kumipuyo], _enemy_state: &PlayerState) -> Decision {
+            self.num_thinks += 1;
+            Decision::new(seq.len(), 0)
+        }
+    }
+
+    fn new_client() -> Client<CountingAI> {
+        Client::new(CountingAI { num_games: 0, num_thinks: 0, last_result: None })
+    }
+
+    #[test]
+    fn test_decision_request() {
+        let mut client = new_client();
+
+        // A request without decision_request is answered with only ID.
+        let resp = client.handle_frame_request(&FrameRequest::parse("ID=1 YP=RRBB OP=RRBB").unwrap());
+        assert_eq!("ID=1", resp.unwrap().unwrap().to_string());
+        assert_eq!(1, client.ai().num_games);
+
+        // decision_request for "you" is STATE bit 6.
+        let resp = client.handle_frame_request(&FrameRequest::parse("ID=2 STATE=64 YP=RRBB OP=RRBB").unwrap());
+        let resp = resp.unwrap().unwrap();
+        assert_eq!(2, resp.frame_id);
+        assert_eq!(Decision::new(2, 0), resp.decision);
+        assert_eq!(1, client.ai().num_thinks);
+        assert_eq!(2, client.my_state().decision_request_frame_id);
+        assert_eq!(0, client.enemy_state().decision_request_frame_id);
+    }
+
+    #[test]
+    fn test_pre_decision_request() {
+        let mut client = new_client();
+        let field = "......".repeat(12) + "RB....";
+
+        let req = FrameRequest::parse("ID=1 STATE=64 YP=RBYYGG").unwrap();
+        assert_eq!(Decision::new(3, 0), client.handle_frame_request(&req).unwrap().unwrap().decision);
+        assert_eq!(1, client.ai().num_thinks);
+
+        // pre_decision_request for "you" is STATE bit 4.
+        let req = FrameRequest::parse(&format!("ID=2 STATE=16 YF={} YP=RBYYGG", field)).unwrap();
+        assert_eq!("ID=2", client.handle_frame_request(&req).unwrap().unwrap().to_string());
+        assert_eq!(2, client.ai().num_thinks);
+
+        // When the field is unchanged, the pre-thought decision is used.
+        let req = FrameRequest::parse(&format!("ID=3 STATE=64 YF={} YP=YYGGRR", field)).unwrap();
+        assert_eq!(Decision::new(2, 0), client.handle_frame_request(&req).unwrap().unwrap().decision);
+        assert_eq!(2, client.ai().num_thinks);
+
+        // When the field has changed, the AI thinks again.
+        let req = FrameRequest::parse(&format!("ID=4 STATE=16 YF={} YP=YYGGRR", field)).unwrap();
+        assert_eq!("ID=4", client.handle_frame_request(&req).unwrap().unwrap().to_string());
+        assert_eq!(3, client.ai().num_thinks);
+        let req = FrameRequest::parse("ID=5 STATE=64 YP=GGRR").unwrap();
+        assert_eq!(Decision::new(2, 0), client.handle_frame_request(&req).unwrap().unwrap().decision);
+        assert_eq!(4, client.ai().num_thinks);
+    }
+
+    #[test]
+    fn test_game_end() {
+        let mut client = new_client();
+
+        client.handle_frame_request(&FrameRequest::parse("ID=1").unwrap()).unwrap();
+        assert!(client.handle_frame_request(&FrameRequest::parse("ID=2 END=1").unwrap()).unwrap().is_none());
+        assert_eq!(Some(GameResult::P1Win), client.ai().last_result);
+
+        client.handle_frame_request(&FrameRequest::parse("ID=1").unwrap()).unwrap();
+        assert_eq!(2, client.ai().num_games);
+        assert_eq!(CoreField::from_plain_field(&PuyoPlainField::new()), client.my_state().field);
+    }
+
+    #[test]
+    fn test_color_map() {
+        let mut client = new_client();
+
+        // RealColor is mapped to PuyoColor by first appearance.
+        let req = FrameRequest::parse("ID=1 YF=PG.... YP=PPGGRRBB").unwrap();
+        client.handle_frame_request(&req).unwrap();
+        assert_eq!(CoreField::from_str("RB...."), client.my_state().field);
+        assert_eq!(Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::YELLOW), client.my_state().seq[2]);
+
+        // The 5th color cannot be mapped.
+        assert!(client.handle_frame_request(&FrameRequest::parse("ID=2 OP=YY").unwrap()).is_err());
+
+        // The map is cleared when a new game begins.
+        client.handle_frame_request(&FrameRequest::parse("ID=3 END=1").unwrap()).unwrap();
+        client.handle_frame_request(&FrameRequest::parse("ID=1 YP=YYPP").unwrap()).unwrap();
+        assert_eq!(Kumipuyo::new(PuyoColor::RED, PuyoColor::RED), client.my_state().seq[0]);
+    }
+}
@@ -0,0 +1,13 @@
+//! puyoai-client provides the runtime shared by AI clients.
+//! An AI implements `AI`, and `Client` talks with the server on its behalf.
+
+extern crate puyoai_core;
+extern crate puyoai_data;
+
+pub mod ai;
+pub mod client;
+pub mod player_state;
+
+pub use ai::AI;
+pub use client::Client;
+pub use player_state::PlayerState;
@@ -0,0 +1,91 @@
+use puyoai_core::color_map::ColorMap;
+use puyoai_core::field::CoreField;
+use puyoai_core::kumipuyo::{Kumipuyo, KumipuyoPos};
+use puyoai_data::PlayerFrameRequest;
+
+/// PlayerState is the state of a player, which is tracked from FrameRequests.
+#[derive(Clone, Debug)]
+pub struct PlayerState {
+    pub field: CoreField,
+    pub seq: Vec<Kumipuyo>,
+    pub pos: KumipuyoPos,
+    pub score: u32,
+    pub ojama: u32,
+    /// true while the player is firing a rensa.
+    pub rensa_ongoing: bool,
+    /// The frame id when decision_request was sent to the player last.
+    pub decision_request_frame_id: i32,
+}
+
+impl PlayerState {
+    pub fn new() -> PlayerState {
+        PlayerState {
+            field: CoreField::new(),
+            seq: Vec::new(),
+            pos: KumipuyoPos::new(0, 0, 0),
+            score: 0,
+            ojama: 0,
+            rensa_ongoing: false,
+            decision_request_frame_id: 0,
+        }
+    }
+
+    /// Updates the state with `req`. The colors in `req` are mapped to PuyoColor with `color_map`,
+    /// which should be shared by both players in a game.
+    pub fn update(&mut self, frame_id: i32, req: &PlayerFrameRequest, color_map: &mut ColorMap) -> Result<(), String> {
+        self.field = CoreField::from_plain_field(&try!(color_map.map_field(&req.field)));
+        self.seq = try!(color_map.map_seq(&req.seq));
+        self.pos = req.pos;
+        self.score = req.score;
+        self.ojama = req.ojama;
+
+        if req.event.puyo_erased {
+            self.rensa_ongoing = true;
+        }
+        if req.event.decision_request {
+            self.rensa_ongoing = false;
+            self.decision_request_frame_id = frame_id;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PlayerState;
+    use puyoai_core::color::{PuyoColor, RealColor};
+    use puyoai_core::color_map::ColorMap;
+    use puyoai_core::field::{CoreField, RealPlainField};
+    use puyoai_core::kumipuyo::{Kumipuyo, Kumireal};
+    use puyoai_data::PlayerFrameRequest;
+
+    #[test]
+    fn test_update() {
+        let mut state = PlayerState::new();
+        let mut color_map = ColorMap::new();
+
+        let mut req = PlayerFrameRequest::new();
+        req.field = RealPlainField::from_str("PPGG..");
+        req.seq = vec![Kumireal::new(RealColor::GREEN, RealColor::YELLOW)];
+        req.score = 40;
+        req.event.puyo_erased = true;
+        state.update(10, &req, &mut color_map).unwrap();
+
+        // The colors are mapped in order of first appearance.
+        assert_eq!(CoreField::from_str("RRBB.."), state.field);
+        assert_eq!(1, state.field.height(1));
+        assert_eq!(vec![Kumipuyo::new(PuyoColor::BLUE, PuyoColor::YELLOW)], state.seq);
+        assert_eq!(40, state.score);
+        assert!(state.rensa_ongoing);
+
+        req.event.puyo_erased = false;
+        req.event.decision_request = true;
+        state.update(20, &req, &mut color_map).unwrap();
+        assert!(!state.rensa_ongoing);
+        assert_eq!(20, state.decision_request_frame_id);
+
+        // CoreField has only 4 colors.
+        req.seq = vec![Kumireal::new(RealColor::RED, RealColor::BLUE)];
+        assert!(state.update(30, &req, &mut color_map).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt::Display;
 
-pub trait Color : Clone + Copy + PartialEq<Self> + Display {
+pub trait Color : Clone + Copy + PartialEq<Self> + Display + 'static {
     fn from_byte(b: u8) -> Self;
     fn empty_color() -> Self;
     fn ojama_color() -> Self;
@@ -0,0 +1,139 @@
+use color::{Color, PuyoColor, RealColor};
+use field::{self, PuyoPlainField, RealPlainField};
+use kumipuyo::{Kumipuyo, Kumireal};
+
+/// ColorMap maps RealColor, which is the color on the screen, to PuyoColor.
+///
+/// A normal RealColor is assigned to a normal PuyoColor in order of first appearance,
+/// and the assignment stays the same until `clear` is called, i.e. for the whole game.
+#[derive(Clone, Debug)]
+pub struct ColorMap {
+    // colors[c as usize] is the PuyoColor for RealColor c.
+    colors: [Option<PuyoColor>; 8],
+    num_assigned: usize,
+}
+
+impl ColorMap {
+    pub fn new() -> ColorMap {
+        ColorMap {
+            colors: [None; 8],
+            num_assigned: 0,
+        }
+    }
+
+    /// Forgets all the assignments. Call this when a new game starts.
+    pub fn clear(&mut self) {
+        *self = ColorMap::new();
+    }
+
+    /// Returns the PuyoColor for `c` without assigning a new one.
+    pub fn get(&self, c: RealColor) -> Option<PuyoColor> {
+        match c {
+            RealColor::EMPTY => Some(PuyoColor::EMPTY),
+            RealColor::WALL => Some(PuyoColor::WALL),
+            RealColor::OJAMA => Some(PuyoColor::OJAMA),
+            _ => self.colors[c as usize],
+        }
+    }
+
+    /// Returns the PuyoColor for `c`. If `c` is seen for the first time, a new PuyoColor
+    /// is assigned. None is returned when all the normal PuyoColors are already assigned.
+    pub fn map(&mut self, c: RealColor) -> Option<PuyoColor> {
+        if let Some(pc) = self.get(c) {
+            return Some(pc);
+        }
+
+        let normal_colors = PuyoColor::all_normal_colors();
+        if self.num_assigned >= normal_colors.len() {
+            return None;
+        }
+
+        let pc = normal_colors[self.num_assigned];
+        self.colors[c as usize] = Some(pc);
+        self.num_assigned += 1;
+        Some(pc)
+    }
+
+    pub fn map_kumipuyo(&mut self, kp: &Kumireal) -> Result<Kumipuyo, String> {
+        let axis = try!(self.map_or_error(kp.axis()));
+        let child = try!(self.map_or_error(kp.child()));
+        Ok(Kumipuyo::new(axis, child))
+    }
+
+    pub fn map_seq(&mut self, seq: &[Kumireal]) -> Result<Vec<Kumipuyo>, String> {
+        let mut result = Vec::with_capacity(seq.len());
+        for kp in seq {
+            result.push(try!(self.map_kumipuyo(kp)));
+        }
+        Ok(result)
+    }
+
+    /// Maps every puyo in `field`. New colors are assigned from the bottom row,
+    /// and from left to right in a row.
+    pub fn map_field(&mut self, field: &RealPlainField) -> Result<PuyoPlainField, String> {
+        let mut result = PuyoPlainField::new();
+        for y in 1 .. field::MAP_HEIGHT - 1 {
+            for x in 1 .. field::WIDTH + 1 {
+                let c = try!(self.map_or_error(field.color(x, y)));
+                result.set_color(x, y, c);
+            }
+        }
+        Ok(result)
+    }
+
+    fn map_or_error(&mut self, c: RealColor) -> Result<PuyoColor, String> {
+        self.map(c).ok_or(format!("no PuyoColor is left for {}", c.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ColorMap;
+    use color::{PuyoColor, RealColor};
+    use field::RealPlainField;
+    use kumipuyo::{Kumipuyo, Kumireal};
+
+    #[test]
+    fn test_map() {
+        let mut cm = ColorMap::new();
+        assert_eq!(Some(PuyoColor::EMPTY), cm.map(RealColor::EMPTY));
+        assert_eq!(Some(PuyoColor::OJAMA), cm.map(RealColor::OJAMA));
+        assert_eq!(None, cm.get(RealColor::PURPLE));
+
+        assert_eq!(Some(PuyoColor::RED), cm.map(RealColor::PURPLE));
+        assert_eq!(Some(PuyoColor::BLUE), cm.map(RealColor::YELLOW));
+        assert_eq!(Some(PuyoColor::RED), cm.map(RealColor::PURPLE));
+        assert_eq!(Some(PuyoColor::YELLOW), cm.map(RealColor::RED));
+        assert_eq!(Some(PuyoColor::GREEN), cm.map(RealColor::BLUE));
+        assert_eq!(None, cm.map(RealColor::GREEN));
+        assert_eq!(Some(PuyoColor::BLUE), cm.get(RealColor::YELLOW));
+
+        cm.clear();
+        assert_eq!(Some(PuyoColor::RED), cm.map(RealColor::GREEN));
+    }
+
+    #[test]
+    fn test_map_seq_and_field() {
+        let mut cm = ColorMap::new();
+
+        let seq = vec![Kumireal::new(RealColor::GREEN, RealColor::PURPLE),
+                       Kumireal::new(RealColor::PURPLE, RealColor::PURPLE)];
+        assert_eq!(vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+                        Kumipuyo::new(PuyoColor::BLUE, PuyoColor::BLUE)],
+                   cm.map_seq(&seq).unwrap());
+
+        let field = RealPlainField::from_str(concat!(
+            "Y.....",
+            "PGO..."));
+        let pf = cm.map_field(&field).unwrap();
+        assert_eq!(PuyoColor::BLUE, pf.color(1, 1));
+        assert_eq!(PuyoColor::RED, pf.color(2, 1));
+        assert_eq!(PuyoColor::OJAMA, pf.color(3, 1));
+        assert_eq!(PuyoColor::YELLOW, pf.color(1, 2));
+        assert_eq!(PuyoColor::EMPTY, pf.color(2, 2));
+
+        // RED and BLUE are the 4th and 5th colors.
+        let seq = vec![Kumireal::new(RealColor::RED, RealColor::BLUE)];
+        assert!(cm.map_seq(&seq).is_err());
+    }
+}
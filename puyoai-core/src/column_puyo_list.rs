@@ -3,6 +3,7 @@ use small_int_set::SmallIntSet;
 
 const MAX_SIZE: usize = 8;
 
+#[derive(Clone)]
 pub struct ColumnPuyoList {
     size: [usize; 6],
     puyo: [[PuyoColor; MAX_SIZE]; 6],
@@ -1,5 +1,9 @@
-use field::{FieldHeight, FieldIsEmpty};
+use std::collections::HashMap;
+
+use control::{Key, KeySet};
+use field::{self, FieldHeight, FieldIsEmpty};
 use decision::Decision;
+use kumipuyo::KumipuyoMovingState;
 
 pub struct PuyoController {
 }
@@ -67,14 +71,304 @@ impl PuyoController {
 
         return true;
     }
+
+    /// Returns the shortest key set sequence that puts the kumipuyo at `decision` from
+    /// the initial position. One KeySet is for one frame, and the kumipuyo is grounded
+    /// on the last frame. None is returned if `decision` is unreachable.
+    pub fn find_key_set_seq<F: FieldIsEmpty>(&self, field: &F, decision: &Decision) -> Option<Vec<KeySet>> {
+        self.find_key_set_seq_from(field, &KumipuyoMovingState::initial(), decision)
+    }
+
+    /// Same as `find_key_set_seq`, but starts from `state`.
+    pub fn find_key_set_seq_from<F: FieldIsEmpty>(&self, field: &F, state: &KumipuyoMovingState,
+                                                  decision: &Decision) -> Option<Vec<KeySet>> {
+        debug_assert!(decision.is_valid());
+
+        if state.grounded {
+            return if is_at(state, decision) { Some(Vec::new()) } else { None };
+        }
+
+        // The axis is grounded on the lowest empty cell of its column or above.
+        let x = decision.axis_x();
+        let floor_y = (1 .. field::MAP_HEIGHT).find(|&y| field.is_empty(x, y)).unwrap_or(field::MAP_HEIGHT) as i32;
+
+        let mut result = None;
+        search(field, state, &SearchMoves::shortest(), Some(floor_y), |nodes, i| {
+            if !is_at(&nodes[i].state, decision) {
+                return false;
+            }
+            result = Some(key_set_seq_to(nodes, i));
+            true
+        });
+
+        result
+    }
+
+    /// Searches the moves of the kumipuyo from the initial position on `field`,
+    /// including wall kicks, quick turns and climbing by turns.
+    /// Returns every reachable decision with the minimal frames to be grounded there,
+    /// in the order of Decision. To keep the search small, arrow and turn are pressed only
+    /// when both are accepted, so the frames can be a few more than the best keys.
+    pub fn find_reachable_decisions<F: FieldIsEmpty>(&self, field: &F) -> Vec<(Decision, usize)> {
+        let mut frames: [[Option<usize>; 4]; 7] = [[None; 4]; 7];
+        search(field, &KumipuyoMovingState::initial(), &SearchMoves::shortest(), None, |nodes, i| {
+            let node = &nodes[i];
+            let (x, r) = (node.state.pos.axis_x() as usize, node.state.pos.rot() as usize);
+            if frames[x][r].is_none() {
+                frames[x][r] = Some(node.frames);
+            }
+            false
+        });
+
+        let mut result = Vec::new();
+        for x in 1 .. 7 {
+            for r in 0 .. 4 {
+                if let Some(f) = frames[x][r] {
+                    result.push((Decision::new(x, r), f));
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns the reachable decisions on `field` in the order of Decision. While the field
+    /// is low, everything is reachable. Otherwise, this searches the moves like
+    /// `find_reachable_decisions`, but without Down, since this is called for many fields in plans.
+    pub fn reachable_decisions<F: FieldHeight + FieldIsEmpty>(&self, field: &F) -> Vec<Decision> {
+        if (1 .. 7).all(|x| field.height(x) <= 10) {
+            return Decision::all_valid_decisions().to_vec();
+        }
+
+        search_reachable_decisions(field)
+    }
+}
+
+fn search_reachable_decisions<F: FieldIsEmpty>(field: &F) -> Vec<Decision> {
+    let mut reachables = [[false; 4]; 7];
+    search(field, &KumipuyoMovingState::initial(), &SearchMoves::reachable(), None, |nodes, i| {
+        let pos = &nodes[i].state.pos;
+        reachables[pos.axis_x() as usize][pos.rot() as usize] = true;
+        false
+    });
+
+    let mut result = Vec::new();
+    for x in 1 .. 7 {
+        for r in 0 .. 4 {
+            if reachables[x][r] {
+                result.push(Decision::new(x, r));
+            }
+        }
+    }
+    result
+}
+
+// The edges of the search. An edge presses one of `key_sets` for a frame, and then holds one of
+// `helds` until both arrow and turn are accepted again, so the frames of the edge are its weight.
+// Skipping the frames while they are prohibited keeps the counters from multiplying the states,
+// at the cost of never pressing arrow and turn at different frames in between.
+struct SearchMoves {
+    key_sets: Vec<KeySet>,
+    helds: Vec<KeySet>,
+}
+
+impl SearchMoves {
+    // The moves to find the minimal frames.
+    fn shortest() -> SearchMoves {
+        SearchMoves {
+            key_sets: key_set_candidates(),
+            helds: vec![KeySet::new(), KeySet::from_key(Key::Down)],
+        }
+    }
+
+    // The moves without Down. They are slower, but still reach the same decisions
+    // in a fraction of the states.
+    fn reachable() -> SearchMoves {
+        SearchMoves {
+            key_sets: key_set_candidates().into_iter().filter(|k| !k.has_key(Key::Down)).collect(),
+            helds: vec![KeySet::new()],
+        }
+    }
+}
+
+// Returns true if an edge keeps holding at `state`.
+fn waits(state: &KumipuyoMovingState) -> bool {
+    !state.grounded && (state.rest_frames_turn_prohibited > 0 || state.rest_frames_arrow_prohibited > 0)
+}
+
+// A node of the search. The kumipuyo gets to `state` from nodes[prev] by pressing `key_set`
+// for a frame and then `held` for the rest of `num_frames` frames.
+struct SearchNode {
+    state: KumipuyoMovingState,
+    prev: usize,
+    key_set: KeySet,
+    held: KeySet,
+    num_frames: usize,
+    frames: usize,
+}
+
+// Searches the moves from `start` in the order of frames, i.e. Dijkstra's algorithm over
+// the states where `moves` can press keys. With `floor_y`, the lowest row where the axis can be
+// grounded, this is A* with the rows left to fall as the estimate, since the kumipuyo falls
+// at most a row per frame.
+// `callback` is called with the nodes and the index of a grounded node in the order of frames,
+// so a placement is found first with the minimal frames. The search stops when `callback`
+// returns true.
+fn search<F, C>(field: &F, start: &KumipuyoMovingState, moves: &SearchMoves, floor_y: Option<i32>, mut callback: C)
+    where F: FieldIsEmpty, C: FnMut(&[SearchNode], usize) -> bool {
+    let estimate = |state: &KumipuyoMovingState| {
+        match floor_y {
+            Some(y) if state.pos.axis_y() > y => (state.pos.axis_y() - y) as usize,
+            _ => 0,
+        }
+    };
+
+    let mut nodes = vec![SearchNode {
+        state: *start,
+        prev: 0,
+        key_set: KeySet::new(),
+        held: KeySet::new(),
+        num_frames: 0,
+        frames: 0,
+    }];
+    let mut min_frames = HashMap::new();
+    min_frames.insert(search_key(start), 0);
+    // queues[c] has the nodes whose frames plus the estimate are c.
+    let mut queues: Vec<Vec<usize>> = vec![Vec::new(); estimate(start) + 1];
+    queues[estimate(start)].push(0);
+
+    let mut c = 0;
+    while c < queues.len() {
+        let mut k = 0;
+        while k < queues[c].len() {
+            let i = queues[c][k];
+            k += 1;
+            if min_frames[&search_key(&nodes[i].state)] < nodes[i].frames {
+                continue;
+            }
+            if nodes[i].state.grounded {
+                if callback(&nodes, i) {
+                    return;
+                }
+                continue;
+            }
+
+            for key_set in &moves.key_sets {
+                let mut pressed = nodes[i].state;
+                pressed.move_kumipuyo(field, key_set);
+                let helds = if waits(&pressed) { &moves.helds[..] } else { &moves.helds[.. 1] };
+
+                for held in helds {
+                    let mut next = pressed;
+                    let mut num_frames = 1;
+                    while waits(&next) {
+                        next.move_kumipuyo(field, held);
+                        num_frames += 1;
+                    }
+
+                    let frames = nodes[i].frames + num_frames;
+                    let key = search_key(&next);
+                    if let Some(&g) = min_frames.get(&key) {
+                        if g <= frames {
+                            continue;
+                        }
+                    }
+                    min_frames.insert(key, frames);
+                    nodes.push(SearchNode {
+                        state: next,
+                        prev: i,
+                        key_set: *key_set,
+                        held: *held,
+                        num_frames: num_frames,
+                        frames: frames,
+                    });
+                    let cost = frames + estimate(&next);
+                    while queues.len() <= cost {
+                        queues.push(Vec::new());
+                    }
+                    queues[cost].push(nodes.len() - 1);
+                }
+            }
+        }
+        c += 1;
+    }
+}
+
+// Returns the state to deduplicate `state` in the search. The frames left to accept a quick turn
+// only tell whether a quick turn is pending, since otherwise every blocked turn multiplies
+// the states, and the earliest pending one is enough in practice.
+fn search_key(state: &KumipuyoMovingState) -> KumipuyoMovingState {
+    let mut key = *state;
+    if key.rest_frames_to_accept_quickturn > 0 {
+        key.rest_frames_to_accept_quickturn = 1;
+    }
+    key
+}
+
+// Returns the key sets from the start to nodes[i].
+fn key_set_seq_to(nodes: &[SearchNode], i: usize) -> Vec<KeySet> {
+    let mut result = Vec::new();
+    let mut j = i;
+    while j != 0 {
+        for _ in 1 .. nodes[j].num_frames {
+            result.push(nodes[j].held);
+        }
+        result.push(nodes[j].key_set);
+        j = nodes[j].prev;
+    }
+    result.reverse();
+    result
+}
+
+fn is_at(state: &KumipuyoMovingState, decision: &Decision) -> bool {
+    state.pos.axis_x() as usize == decision.axis_x() && state.pos.rot() as usize == decision.rot()
+}
+
+// Every meaningful combination of the keys for one frame. Pressing both arrows or both
+// turns at once is the same as pressing one of them, so such combinations are omitted.
+fn key_set_candidates() -> Vec<KeySet> {
+    let mut candidates = Vec::new();
+    for down in &[true, false] {
+        for arrow in &[None, Some(Key::Left), Some(Key::Right)] {
+            for turn in &[None, Some(Key::RightTurn), Some(Key::LeftTurn)] {
+                let mut key_set = KeySet::new();
+                if *down {
+                    key_set.set_key(Key::Down);
+                }
+                if let Some(k) = *arrow {
+                    key_set.set_key(k);
+                }
+                if let Some(k) = *turn {
+                    key_set.set_key(k);
+                }
+                candidates.push(key_set);
+            }
+        }
+    }
+
+    candidates
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use control::KeySet;
     use field::{FieldWithHeight, PuyoPlainField};
     use decision::Decision;
+    use kumipuyo::KumipuyoMovingState;
+
+    // Applies `key_set_seq` from the initial position, and checks the kumipuyo is
+    // grounded at `decision` on the last frame.
+    fn assert_key_set_seq(field: &PuyoPlainField, decision: &Decision, key_set_seq: &[KeySet]) {
+        let mut state = KumipuyoMovingState::initial();
+        for (i, key_set) in key_set_seq.iter().enumerate() {
+            assert!(!state.grounded, "grounded before frame {}", i);
+            state.move_kumipuyo(field, key_set);
+        }
+        assert!(state.grounded);
+        assert_eq!(decision.axis_x(), state.pos.axis_x() as usize);
+        assert_eq!(decision.rot(), state.pos.rot() as usize);
+    }
 
     #[test]
     fn test_is_reachable_empty_field() {
@@ -297,4 +591,122 @@ mod tests {
             assert_eq!(pc.is_reachable(&f, d), reachables.contains(d), "d={:?}", d);
         }
     }
+
+    #[test]
+    fn test_find_key_set_seq_empty_field() {
+        let pc = PuyoController::new();
+        let field = PuyoPlainField::new();
+
+        let mut lens = Vec::new();
+        for d in &[Decision::new(3, 0), Decision::new(1, 0), Decision::new(6, 3), Decision::new(3, 2)] {
+            let key_set_seq = pc.find_key_set_seq(&field, d).unwrap();
+            assert_key_set_seq(&field, d, &key_set_seq);
+            lens.push(key_set_seq.len());
+        }
+
+        // Moving to the wall takes longer than dropping at once.
+        assert!(lens[0] <= lens[1]);
+    }
+
+    #[test]
+    fn test_find_key_set_seq_quick_turn() {
+        let pc = PuyoController::new();
+        let field = PuyoPlainField::from_str(concat!(
+            ".O.O..", // 12
+            ".O.O..",
+            ".O.O..",
+            ".O.O..",
+            ".O.O..", // 8
+            ".O.O..",
+            ".O.O..",
+            ".O.O..",
+            ".O.O..", // 4
+            ".O.O..",
+            ".O.O..",
+            ".O.O.."));
+
+        let d = Decision::new(3, 2);
+        let key_set_seq = pc.find_key_set_seq(&field, &d).unwrap();
+        assert_key_set_seq(&field, &d, &key_set_seq);
+
+        // The 4th column is filled up to the 13th row, so nothing can go over it.
+        let field = PuyoPlainField::from_str(&"...O..".repeat(13));
+        assert!(pc.find_key_set_seq(&field, &Decision::new(5, 0)).is_none());
+        assert!(pc.find_key_set_seq(&field, &Decision::new(2, 0)).is_some());
+    }
+
+    #[test]
+    fn test_find_reachable_decisions_empty_field() {
+        let pc = PuyoController::new();
+        let field = PuyoPlainField::new();
+
+        let reachables = pc.find_reachable_decisions(&field);
+        assert_eq!(22, reachables.len());
+        for &(ref d, _) in &reachables {
+            assert!(d.is_valid());
+        }
+        for &(ref d, frames) in reachables.iter().filter(|&&(ref d, _)| d.axis_x() == 1) {
+            assert_eq!(pc.find_key_set_seq(&field, d).unwrap().len(), frames);
+        }
+    }
+
+    #[test]
+    fn test_find_reachable_decisions_climbing() {
+        let pc = PuyoController::new();
+        let field = PuyoPlainField::from_str(concat!(
+            "O....O", // 12
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO", // 8
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO", // 4
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO"));
+
+        // The kumipuyo climbs onto the 1st and 6th columns by turning, and is put on the 13th row.
+        let reachables: Vec<Decision> = pc.find_reachable_decisions(&field).into_iter().map(|(d, _)| d).collect();
+        assert!(reachables.contains(&Decision::new(1, 0)));
+        assert!(reachables.contains(&Decision::new(6, 0)));
+        assert!(!reachables.contains(&Decision::new(1, 2)));
+        assert!(!reachables.contains(&Decision::new(6, 2)));
+
+        let f = FieldWithHeight::from_field(field);
+        assert_eq!(reachables, pc.reachable_decisions(&f));
+    }
+
+    #[test]
+    fn test_reachable_decisions_low_field() {
+        let pc = PuyoController::new();
+        let f = FieldWithHeight::from_field(PuyoPlainField::new());
+        assert_eq!(22, pc.reachable_decisions(&f).len());
+    }
+
+    #[test]
+    fn test_reachable_decisions_shortcut() {
+        let pc = PuyoController::new();
+
+        // While no column is higher than 10, the search finds every decision, so
+        // reachable_decisions can skip it.
+        let fields = [
+            "OO..OO".repeat(9) + "OOO.OO",
+            ".OOOO.".repeat(10),
+        ];
+        for s in &fields {
+            let field = PuyoPlainField::from_str(s);
+            assert_eq!(22, search_reachable_decisions(&field).len(), "field={}", s);
+            assert_eq!(22, pc.reachable_decisions(&FieldWithHeight::from_field(field)).len());
+        }
+
+        // Once a column is higher, the search decides. Searching without Down finds the same
+        // decisions as find_reachable_decisions.
+        let field = PuyoPlainField::from_str(&"...O..".repeat(13));
+        let reachables = pc.reachable_decisions(&FieldWithHeight::from_field(field.clone()));
+        assert!(reachables.len() < 22);
+        let searched: Vec<Decision> = pc.find_reachable_decisions(&field).into_iter().map(|(d, _)| d).collect();
+        assert_eq!(searched, reachables);
+    }
 }
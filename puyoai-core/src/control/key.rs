@@ -22,6 +22,18 @@ impl Key {
             _ => Err(format!("Unknown key character: {}", c)),
         }
     }
+
+    pub fn to_char(&self) -> char {
+        match *self {
+            Key::Up => '^',
+            Key::Right => '>',
+            Key::Down => 'v',
+            Key::Left => '<',
+            Key::RightTurn => 'A',
+            Key::LeftTurn => 'B',
+            Key::Start => 'S',
+        }
+    }
 }
 
 #[cfg(test)]
@@ -40,4 +52,12 @@ mod tests {
 
         assert!(Key::parse_char('_').is_err());
     }
+
+    #[test]
+    fn test_to_char() {
+        let keys = [Key::Up, Key::Right, Key::Down, Key::Left, Key::RightTurn, Key::LeftTurn, Key::Start];
+        for k in keys.iter() {
+            assert_eq!(*k, Key::parse_char(k.to_char()).unwrap());
+        }
+    }
 }
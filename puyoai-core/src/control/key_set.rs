@@ -33,6 +33,18 @@ impl KeySet {
     pub fn has_key(&self, k: Key) -> bool {
         (self.keys & (1 << (k as usize))) != 0
     }
+
+    pub fn to_string(&self) -> String {
+        const ALL_KEYS: [Key; 7] = [Key::Up, Key::Right, Key::Down, Key::Left,
+                                    Key::RightTurn, Key::LeftTurn, Key::Start];
+        let mut result = String::new();
+        for k in ALL_KEYS.iter() {
+            if self.has_key(*k) {
+                result.push(k.to_char());
+            }
+        }
+        result
+    }
 }
 
 pub fn parse_keysetseq(s: &str) -> Result<Vec<KeySet>, String> {
@@ -47,6 +59,12 @@ pub fn parse_keysetseq(s: &str) -> Result<Vec<KeySet>, String> {
     Ok(keysetseq)
 }
 
+/// Returns the string that `parse_keysetseq` can parse.
+pub fn keysetseq_to_string(keysetseq: &[KeySet]) -> String {
+    let strs: Vec<String> = keysetseq.iter().map(|ks| ks.to_string()).collect();
+    strs.join(",")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,4 +120,11 @@ mod tests {
 
         assert_eq!(expected, parse_keysetseq(">A,>A,<B,<B,vA,vB").unwrap().as_slice());
     }
+
+    #[test]
+    fn test_keysetseq_to_string() {
+        let keysetseq = parse_keysetseq(">A,v,,<B").unwrap();
+        assert_eq!(">A,v,,<B", keysetseq_to_string(&keysetseq));
+        assert_eq!("", KeySet::new().to_string());
+    }
 }
@@ -388,14 +388,8 @@ impl BitField {
     }
 
     pub fn drop_after_vanish<T: RensaTracker>(&mut self, erased: FieldBit, tracker: &mut T) -> usize {
-        // Set 1 at non-empty position.
-        // Remove 1 bits from the positions where they are erased.
-        let nonempty = mm_andnot_si128(erased.as_m128i(), (self.m[0] | self.m[1] | self.m[2]).as_m128i());
-
-        // Find the holes. The number of holes for each column is the number of
-        // drops of the column.
-        let holes = mm_and_si128(sseext::mm_porr_epi16(nonempty), erased.as_m128i());
-        let num_holes = sseext::mm_popcnt_epi16(holes);
+        // The number of holes for each column is the number of drops of the column.
+        let num_holes = sseext::mm_popcnt_epi16(self.holes(erased).as_m128i());
         let max_drops = sseext::mm_hmax_epu16(num_holes);
 
         self.drop_after_vanish_fast(erased, tracker);
@@ -404,6 +398,8 @@ impl BitField {
     }
 
     pub fn drop_after_vanish_fast<T: RensaTracker>(&mut self, erased: FieldBit, tracker: &mut T) {
+        tracker.track_holes(&self.holes(erased));
+
         let ones = sseext::mm_setone_si128();
 
         let t = mm_xor_si128(erased.as_m128i(), ones);
@@ -441,6 +437,14 @@ impl BitField {
 
         tracker.track_drop(old_low_bits, old_high_bits, new_low_bits, new_high_bits);
     }
+
+    // Returns the holes, i.e. the erased positions below a puyo which is not erased.
+    fn holes(&self, erased: FieldBit) -> FieldBit {
+        // Set 1 at non-empty position.
+        // Remove 1 bits from the positions where they are erased.
+        let nonempty = mm_andnot_si128(erased.as_m128i(), (self.m[0] | self.m[1] | self.m[2]).as_m128i());
+        FieldBit::new(mm_and_si128(sseext::mm_porr_epi16(nonempty), erased.as_m128i()))
+    }
 }
 
 impl FieldIsEmpty for BitField {
@@ -1,8 +1,15 @@
 use color::PuyoColor;
 use column_puyo_list::ColumnPuyoList;
 use decision::Decision;
-use field::{self, BitField, FieldHeight, FieldIsEmpty};
+use field::{self, BitField, FieldHeight, FieldIsEmpty, PuyoPlainField};
 use frame;
+use kumipuyo::Kumipuyo;
+#[cfg(all(target_feature = "avx2", target_feature = "bmi2"))]
+use field_bit::FieldBit;
+#[cfg(all(target_feature = "avx2", target_feature = "bmi2"))]
+use rensa_result::RensaResult;
+#[cfg(all(target_feature = "avx2", target_feature = "bmi2"))]
+use rensa_tracker::RensaNonTracker;
 
 use std;
 
@@ -21,21 +28,28 @@ impl CoreField {
     }
 
     pub fn from_str(s: &str) -> CoreField {
+        CoreField::from_plain_field(&PuyoPlainField::from_str(s))
+    }
+
+    pub fn from_plain_field(pf: &PuyoPlainField) -> CoreField {
         let mut cf = CoreField {
-            field: BitField::from_str(s),
+            field: BitField::from_plain_field(pf.clone()),
             height: [0; 8],
         };
+        cf.update_height();
+        cf
+    }
 
+    fn update_height(&mut self) {
         for x in 1 .. field::WIDTH + 1 {
+            self.height[x] = 0;
             for y in 1 .. 15 {
-                if cf.is_empty(x, y) {
-                    cf.height[x] = (y - 1) as i16;
+                if self.is_empty(x, y) {
+                    self.height[x] = (y - 1) as i16;
                     break
                 }
             }
         }
-
-        cf
     }
 
     pub fn color(&self, x: usize, y: usize) -> PuyoColor {
@@ -107,6 +121,37 @@ impl CoreField {
         true
     }
 
+    /// Puts `c` on (x, y), which must be above the column. The empty cells below (x, y)
+    /// are filled with IRON, a place holder, so that no puyo floats.
+    pub fn set_puyo_on_place_holders(&mut self, x: usize, y: usize, c: PuyoColor) {
+        debug_assert!(self.height(x) < y, "x={} y={}", x, y);
+        debug_assert!(y <= 13, "y={}", y);
+
+        for yy in (self.height(x) + 1)..y {
+            self.field.set_color(x, yy, PuyoColor::IRON);
+        }
+        self.field.set_color(x, y, c);
+        self.height[x] = y as i16;
+    }
+
+    /// Puts `kumipuyo` with `decision`. A puyo on the 14th row disappears.
+    /// Returns false if the kumipuyo cannot be put at all.
+    pub fn drop_kumipuyo(&mut self, decision: &Decision, kumipuyo: &Kumipuyo) -> bool {
+        debug_assert!(decision.is_valid(), "decision {:?} should be valid", decision);
+
+        let (x1, c1, x2, c2) = if decision.rot() == 2 {
+            (decision.child_x(), kumipuyo.child(), decision.axis_x(), kumipuyo.axis())
+        } else {
+            (decision.axis_x(), kumipuyo.axis(), decision.child_x(), kumipuyo.child())
+        };
+
+        if !self.drop_puyo_on_with_max_height(x1, c1, 13) {
+            return false;
+        }
+        self.drop_puyo_on_with_max_height(x2, c2, 13);
+        true
+    }
+
     pub fn drop_column_puyo_list(&mut self, cpl: &ColumnPuyoList) -> bool {
         self.drop_column_puyo_list_with_max_height(cpl, 13)
     }
@@ -193,6 +238,32 @@ impl CoreField {
     }
 }
 
+#[cfg(all(target_feature = "avx2", target_feature = "bmi2"))]
+impl CoreField {
+    pub fn simulate(&mut self) -> RensaResult {
+        let result = self.field.simulate();
+        self.update_height();
+        result
+    }
+
+    /// Vanishes the puyos only once, and drops the rest.
+    /// Returns the score of the vanishment. 0 is returned if nothing vanished.
+    pub fn vanish_drop(&mut self, current_chain: usize) -> usize {
+        let mut tracker = RensaNonTracker::new();
+        let escaped = self.field.escape_invisible();
+
+        let mut erased = unsafe { FieldBit::uninitialized() };
+        let score = self.field.vanish(current_chain, &mut erased, &mut tracker);
+        if score > 0 {
+            self.field.drop_after_vanish(erased, &mut tracker);
+        }
+
+        self.field.recover_invisible(&escaped);
+        self.update_height();
+        score
+    }
+}
+
 impl FieldHeight for CoreField {
     fn height(&self, x: usize) -> usize {
         CoreField::height(self, x)
@@ -211,8 +282,9 @@ mod tests {
     use color::PuyoColor;
     use column_puyo_list::ColumnPuyoList;
     use decision::Decision;
-    use field;
+    use field::{self, PuyoPlainField};
     use frame;
+    use kumipuyo::Kumipuyo;
 
     #[test]
     fn test_constructor() {
@@ -254,6 +326,19 @@ mod tests {
         assert_eq!(0, cf.height(6));
     }
 
+    #[test]
+    fn test_from_plain_field() {
+        let pf = PuyoPlainField::from_str(concat!(
+            "R.....",
+            "RRRB.."));
+        let cf = CoreField::from_plain_field(&pf);
+
+        assert_eq!(CoreField::from_str(concat!("R.....", "RRRB..")), cf);
+        assert_eq!(2, cf.height(1));
+        assert_eq!(1, cf.height(4));
+        assert_eq!(0, cf.height(5));
+    }
+
     #[test]
     fn test_drop_puyo_on() {
         let mut cf = CoreField::from_str(concat!(
@@ -334,6 +419,22 @@ mod tests {
         assert_eq!(cf, expected);
     }
 
+    #[test]
+    fn test_set_puyo_on_place_holders() {
+        let mut cf = CoreField::from_str("R.....");
+        cf.set_puyo_on_place_holders(1, 4, PuyoColor::BLUE);
+        assert_eq!(4, cf.height(1));
+        assert_eq!(PuyoColor::RED, cf.color(1, 1));
+        assert_eq!(PuyoColor::IRON, cf.color(1, 2));
+        assert_eq!(PuyoColor::IRON, cf.color(1, 3));
+        assert_eq!(PuyoColor::BLUE, cf.color(1, 4));
+
+        // Nothing is filled right above the column.
+        cf.set_puyo_on_place_holders(1, 5, PuyoColor::BLUE);
+        assert_eq!(5, cf.height(1));
+        assert_eq!(PuyoColor::BLUE, cf.color(1, 5));
+    }
+
     #[test]
     fn test_is_chigiri_decision_1() {
         let cf = CoreField::new();
@@ -414,4 +515,76 @@ mod tests {
         assert_eq!(6 + frame::FRAMES_TO_MOVE_HORIZONTALLY[1] + frame::FRAMES_GROUNDING + frame::FRAMES_TO_DROP[1] + frame::FRAMES_GROUNDING,
                    cf.frames_to_drop_next(&Decision::new(4, 3)));
     }
+
+    #[test]
+    fn test_drop_kumipuyo() {
+        let kp = Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE);
+
+        let mut cf = CoreField::new();
+        assert!(cf.drop_kumipuyo(&Decision::new(3, 0), &kp));
+        assert_eq!(CoreField::from_str(concat!("..B...", "..R...")), cf);
+
+        let mut cf = CoreField::new();
+        assert!(cf.drop_kumipuyo(&Decision::new(3, 2), &kp));
+        assert_eq!(CoreField::from_str(concat!("..R...", "..B...")), cf);
+
+        let mut cf = CoreField::from_str("R.....");
+        assert!(cf.drop_kumipuyo(&Decision::new(1, 1), &kp));
+        assert_eq!(CoreField::from_str(concat!("R.....", "RB....")), cf);
+        assert_eq!(2, cf.height(1));
+        assert_eq!(1, cf.height(2));
+    }
+
+    #[test]
+    fn test_drop_kumipuyo_on_13th_row() {
+        let kp = Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE);
+        let mut cf = CoreField::from_str(concat!(
+            "O.....", // 12
+            "O.....",
+            "O.....",
+            "O.....",
+            "O.....", // 8
+            "O.....",
+            "O.....",
+            "O.....",
+            "O.....", // 4
+            "O.....",
+            "O.....",
+            "O....."));
+
+        // The child is on the 14th row, so it disappears.
+        assert!(cf.drop_kumipuyo(&Decision::new(1, 0), &kp));
+        assert_eq!(13, cf.height(1));
+        assert_eq!(PuyoColor::RED, cf.color(1, 13));
+        assert_eq!(PuyoColor::EMPTY, cf.color(1, 14));
+
+        assert!(!cf.drop_kumipuyo(&Decision::new(1, 0), &kp));
+    }
+
+    #[cfg(all(target_feature = "avx2", target_feature = "bmi2"))]
+    #[test]
+    fn test_simulate() {
+        let mut cf = CoreField::from_str(concat!(
+            "B.....",
+            "RRRR.."));
+
+        let result = cf.simulate();
+        assert_eq!(1, result.chain);
+        assert_eq!(CoreField::from_str("B....."), cf);
+        assert_eq!(1, cf.height(1));
+        assert_eq!(0, cf.height(2));
+    }
+
+    #[cfg(all(target_feature = "avx2", target_feature = "bmi2"))]
+    #[test]
+    fn test_vanish_drop() {
+        let mut cf = CoreField::from_str(concat!(
+            "G.....",
+            "BBBB..",
+            "RRRRG."));
+
+        assert!(cf.vanish_drop(1) > 0);
+        assert_eq!(CoreField::from_str("G...G."), cf);
+        assert_eq!(0, cf.vanish_drop(2));
+    }
 }
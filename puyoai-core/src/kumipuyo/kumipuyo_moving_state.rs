@@ -0,0 +1,215 @@
+use control::{Key, KeySet};
+use field::FieldIsEmpty;
+use frame;
+use kumipuyo::KumipuyoPos;
+
+/// KumipuyoMovingState is the state of the kumipuyo that a player is controlling.
+/// The rest frames are the frames until the corresponding action is accepted.
+///
+/// A kumipuyo falls by a half row at the middle of the fall interval. While it's
+/// half-stepped, it also occupies the row below `pos`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KumipuyoMovingState {
+    pub pos: KumipuyoPos,
+    pub half_stepped: bool,
+    pub rest_frames_turn_prohibited: usize,
+    pub rest_frames_arrow_prohibited: usize,
+    pub rest_frames_to_accept_quickturn: usize,
+    pub rest_frames_for_freefall: usize,
+    pub grounded: bool,
+}
+
+// HalfStepField is the field seen from a half-stepped kumipuyo.
+// A cell is empty only when the cell below is also empty.
+struct HalfStepField<'a, F: FieldIsEmpty + 'a> {
+    field: &'a F,
+}
+
+impl<'a, F: FieldIsEmpty> FieldIsEmpty for HalfStepField<'a, F> {
+    fn is_empty(&self, x: usize, y: usize) -> bool {
+        y >= 1 && self.field.is_empty(x, y) && self.field.is_empty(x, y - 1)
+    }
+}
+
+impl KumipuyoMovingState {
+    pub fn new(pos: KumipuyoPos) -> KumipuyoMovingState {
+        KumipuyoMovingState {
+            pos: pos,
+            half_stepped: false,
+            rest_frames_turn_prohibited: 0,
+            rest_frames_arrow_prohibited: 0,
+            rest_frames_to_accept_quickturn: 0,
+            rest_frames_for_freefall: frame::FRAMES_FREE_FALL,
+            grounded: false,
+        }
+    }
+
+    /// Returns the state when the kumipuyo has just appeared.
+    pub fn initial() -> KumipuyoMovingState {
+        KumipuyoMovingState::new(KumipuyoPos::initial_pos())
+    }
+
+    /// Proceeds one frame with `key_set` on `field`.
+    /// A turn is applied before an arrow, and then the kumipuyo falls.
+    pub fn move_kumipuyo<F: FieldIsEmpty>(&mut self, field: &F, key_set: &KeySet) {
+        if self.half_stepped {
+            self.move_horizontally(&HalfStepField { field: field }, key_set);
+        } else {
+            self.move_horizontally(field, key_set);
+        }
+        self.fall(field, key_set.has_key(Key::Down));
+    }
+
+    fn move_horizontally<F: FieldIsEmpty>(&mut self, field: &F, key_set: &KeySet) {
+        if self.rest_frames_to_accept_quickturn > 0 {
+            self.rest_frames_to_accept_quickturn -= 1;
+        }
+
+        if self.rest_frames_turn_prohibited > 0 {
+            self.rest_frames_turn_prohibited -= 1;
+        } else if key_set.has_key(Key::RightTurn) {
+            self.turn(field, true);
+        } else if key_set.has_key(Key::LeftTurn) {
+            self.turn(field, false);
+        }
+
+        if self.rest_frames_arrow_prohibited > 0 {
+            self.rest_frames_arrow_prohibited -= 1;
+        } else if key_set.has_key(Key::Right) || key_set.has_key(Key::Left) {
+            let dx = if key_set.has_key(Key::Right) { 1 } else { -1 };
+            if let Some(moved) = self.pos.moved(field, dx) {
+                self.pos = moved;
+            }
+            self.rest_frames_arrow_prohibited = frame::FRAMES_CONTINUOUS_ARROW_PROHIBITED;
+        }
+    }
+
+    fn turn<F: FieldIsEmpty>(&mut self, field: &F, clockwise: bool) {
+        self.rest_frames_turn_prohibited = frame::FRAMES_CONTINUOUS_TURN_PROHIBITED;
+
+        if let Some(turned) = self.pos.turned(field, clockwise) {
+            self.pos = turned;
+            return;
+        }
+
+        // Both sides are blocked. Turning twice quickly swaps the axis and the child.
+        if self.rest_frames_to_accept_quickturn == 0 {
+            self.rest_frames_to_accept_quickturn = frame::FRAMES_QUICKTURN;
+            return;
+        }
+        self.rest_frames_to_accept_quickturn = 0;
+        if let Some(quick) = self.pos.quick_turned(field) {
+            self.pos = quick;
+        }
+    }
+
+    fn fall<F: FieldIsEmpty>(&mut self, field: &F, down: bool) {
+        let interval = if down { frame::FRAMES_TO_DROP_FAST[1] } else { frame::FRAMES_FREE_FALL };
+        if self.rest_frames_for_freefall > interval {
+            self.rest_frames_for_freefall = interval;
+        }
+        if self.rest_frames_for_freefall > 1 {
+            self.rest_frames_for_freefall -= 1;
+            // Falls by a half row at the middle of the interval.
+            if !self.half_stepped && self.rest_frames_for_freefall <= interval / 2 {
+                self.half_stepped = self.pos.fallen(field).is_some();
+            }
+            return;
+        }
+
+        self.rest_frames_for_freefall = interval;
+        self.half_stepped = false;
+        match self.pos.fallen(field) {
+            Some(lower) => self.pos = lower,
+            None => self.grounded = true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KumipuyoMovingState;
+    use control::{Key, KeySet};
+    use field::PuyoPlainField;
+    use frame;
+    use kumipuyo::KumipuyoPos;
+
+    #[test]
+    fn test_move_and_grounded() {
+        let field = PuyoPlainField::new();
+        let mut state = KumipuyoMovingState::initial();
+
+        state.move_kumipuyo(&field, &KeySet::from_keys(&[Key::Left, Key::RightTurn]));
+        assert_eq!(KumipuyoPos::new(2, 12, 1), state.pos);
+
+        // Arrow and turn are prohibited for a few frames.
+        state.move_kumipuyo(&field, &KeySet::from_keys(&[Key::Left, Key::RightTurn]));
+        assert_eq!(KumipuyoPos::new(2, 12, 1), state.pos);
+
+        for _ in 0 .. 1000 {
+            state.move_kumipuyo(&field, &KeySet::from_key(Key::Down));
+            if state.grounded {
+                break;
+            }
+        }
+        assert!(state.grounded);
+        assert_eq!(KumipuyoPos::new(2, 1, 1), state.pos);
+    }
+
+    #[test]
+    fn test_floor_kick() {
+        let field = PuyoPlainField::from_str(concat!(
+            "..O...", // 11
+            "..O...",
+            "..O...",
+            "..O...",
+            "..O...", // 7
+            "..O...",
+            "..O...",
+            "..O...",
+            "..O...", // 3
+            "..O...",
+            "..O..."));
+        let mut state = KumipuyoMovingState::initial();
+
+        state.move_kumipuyo(&field, &KeySet::from_key(Key::RightTurn));
+        state.move_kumipuyo(&field, &KeySet::new());
+        state.move_kumipuyo(&field, &KeySet::new());
+        state.move_kumipuyo(&field, &KeySet::new());
+        state.move_kumipuyo(&field, &KeySet::from_key(Key::RightTurn));
+        assert_eq!(KumipuyoPos::new(3, 13, 2), state.pos);
+    }
+
+    #[test]
+    fn test_half_step() {
+        let field = PuyoPlainField::from_str(concat!(
+            "...O..", // 11
+            "...O..",
+            "...O..",
+            "...O..",
+            "...O..", // 7
+            "...O..",
+            "...O..",
+            "...O..",
+            "...O..", // 3
+            "...O..",
+            "...O.."));
+        let mut state = KumipuyoMovingState::initial();
+
+        for _ in 0 .. frame::FRAMES_FREE_FALL / 2 {
+            state.move_kumipuyo(&field, &KeySet::new());
+        }
+        assert!(state.half_stepped);
+        assert_eq!(KumipuyoPos::new(3, 12, 0), state.pos);
+
+        // The half-stepped kumipuyo is blocked by the puyo on the 11th row of the 4th column.
+        state.move_kumipuyo(&field, &KeySet::from_key(Key::Right));
+        assert_eq!(KumipuyoPos::new(3, 12, 0), state.pos);
+
+        for _ in 0 .. frame::FRAMES_FREE_FALL / 2 - 1 {
+            state.move_kumipuyo(&field, &KeySet::new());
+        }
+        assert!(!state.half_stepped);
+        assert_eq!(KumipuyoPos::new(3, 11, 0), state.pos);
+    }
+}
@@ -1,3 +1,7 @@
+use control::{Key, KeySet};
+use field::{self, FieldIsEmpty};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct KumipuyoPos {
     x: i32,
     y: i32,
@@ -73,4 +77,174 @@ impl KumipuyoPos {
         }
         true
     }
+
+    /// Returns true if the kumipuyo can be at this position on `field`.
+    /// The axis must be at most the 13th row. The child can be on the 14th row.
+    pub fn can_put<F: FieldIsEmpty>(&self, field: &F) -> bool {
+        self.y <= 13 &&
+            is_empty(field, self.axis_x(), self.axis_y()) &&
+            is_empty(field, self.child_x(), self.child_y())
+    }
+
+    /// Returns the position moved by `dx` columns, or None if it's blocked.
+    pub fn moved<F: FieldIsEmpty>(&self, field: &F, dx: i32) -> Option<KumipuyoPos> {
+        let moved = KumipuyoPos::new(self.x + dx, self.y, self.r);
+        if moved.can_put(field) { Some(moved) } else { None }
+    }
+
+    /// Returns the position after turning. When the child hits a wall or a puyo,
+    /// the kumipuyo is kicked to the other side, and when the child hits the floor,
+    /// the kumipuyo is lifted by one row. None is returned if the kumipuyo cannot turn,
+    /// e.g. between two walls, where a quick turn is necessary.
+    pub fn turned<F: FieldIsEmpty>(&self, field: &F, clockwise: bool) -> Option<KumipuyoPos> {
+        let new_r = if clockwise { (self.r + 1) % 4 } else { (self.r + 3) % 4 };
+
+        let turned = KumipuyoPos::new(self.x, self.y, new_r);
+        if turned.can_put(field) {
+            return Some(turned);
+        }
+
+        let kicked = match new_r {
+            1 => KumipuyoPos::new(self.x - 1, self.y, new_r),
+            3 => KumipuyoPos::new(self.x + 1, self.y, new_r),
+            2 => KumipuyoPos::new(self.x, self.y + 1, new_r),
+            _ => return None,
+        };
+        if kicked.can_put(field) { Some(kicked) } else { None }
+    }
+
+    /// Returns the position after a quick turn, which swaps the axis and the child
+    /// of a vertical kumipuyo. None is returned if it's not vertical or blocked.
+    pub fn quick_turned<F: FieldIsEmpty>(&self, field: &F) -> Option<KumipuyoPos> {
+        let quick = match self.r {
+            0 => KumipuyoPos::new(self.x, self.y + 1, 2),
+            2 => KumipuyoPos::new(self.x, self.y - 1, 0),
+            _ => return None,
+        };
+        if quick.can_put(field) { Some(quick) } else { None }
+    }
+
+    /// Returns the position one row lower, or None if the kumipuyo is grounded.
+    pub fn fallen<F: FieldIsEmpty>(&self, field: &F) -> Option<KumipuyoPos> {
+        let lower = KumipuyoPos::new(self.x, self.y - 1, self.r);
+        if lower.can_put(field) { Some(lower) } else { None }
+    }
+
+    /// Returns true if the kumipuyo cannot fall any more.
+    pub fn is_grounded<F: FieldIsEmpty>(&self, field: &F) -> bool {
+        self.fallen(field).is_none()
+    }
+
+    /// Applies `key_set` for one frame, ignoring the frames while turns and arrows are
+    /// prohibited: a turn, an arrow, and then one row by Down. Use KumipuyoMovingState
+    /// to follow the frames exactly.
+    pub fn apply_key_set<F: FieldIsEmpty>(&self, field: &F, key_set: &KeySet) -> KumipuyoPos {
+        let mut pos = *self;
+        if key_set.has_key(Key::RightTurn) {
+            pos = pos.turned(field, true).unwrap_or(pos);
+        } else if key_set.has_key(Key::LeftTurn) {
+            pos = pos.turned(field, false).unwrap_or(pos);
+        }
+
+        if key_set.has_key(Key::Right) {
+            pos = pos.moved(field, 1).unwrap_or(pos);
+        } else if key_set.has_key(Key::Left) {
+            pos = pos.moved(field, -1).unwrap_or(pos);
+        }
+
+        if key_set.has_key(Key::Down) {
+            pos = pos.fallen(field).unwrap_or(pos);
+        }
+
+        pos
+    }
+}
+
+fn is_empty<F: FieldIsEmpty>(field: &F, x: i32, y: i32) -> bool {
+    if x < 0 || field::MAP_WIDTH as i32 <= x || y < 0 || field::MAP_HEIGHT as i32 <= y {
+        return false;
+    }
+    field.is_empty(x as usize, y as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KumipuyoPos;
+    use control::{Key, KeySet};
+    use field::PuyoPlainField;
+
+    #[test]
+    fn test_can_put() {
+        let field = PuyoPlainField::from_str(concat!(
+            "O.....", // 13
+            "O.....", // 12
+            "O.....",
+            "O.....",
+            "O.....",
+            "O.....", // 8
+            "O.....",
+            "O.....",
+            "O.....",
+            "O.....", // 4
+            "O.....",
+            "O.....",
+            "O....."));
+
+        assert!(KumipuyoPos::new(3, 13, 0).can_put(&field));
+        assert!(!KumipuyoPos::new(3, 14, 2).can_put(&field));
+        assert!(!KumipuyoPos::new(2, 12, 3).can_put(&field));
+        assert!(!KumipuyoPos::new(6, 12, 1).can_put(&field));
+    }
+
+    #[test]
+    fn test_turned() {
+        let field = PuyoPlainField::new();
+
+        assert_eq!(Some(KumipuyoPos::new(3, 12, 1)), KumipuyoPos::new(3, 12, 0).turned(&field, true));
+        assert_eq!(Some(KumipuyoPos::new(3, 12, 3)), KumipuyoPos::new(3, 12, 0).turned(&field, false));
+
+        // Kicked by the walls.
+        assert_eq!(Some(KumipuyoPos::new(5, 12, 1)), KumipuyoPos::new(6, 12, 0).turned(&field, true));
+        assert_eq!(Some(KumipuyoPos::new(2, 12, 3)), KumipuyoPos::new(1, 12, 0).turned(&field, false));
+
+        // Kicked by the floor.
+        assert_eq!(Some(KumipuyoPos::new(3, 2, 2)), KumipuyoPos::new(3, 1, 1).turned(&field, true));
+    }
+
+    #[test]
+    fn test_quick_turned() {
+        let field = PuyoPlainField::from_str(concat!(
+            ".O.O..",
+            ".O.O.."));
+
+        let pos = KumipuyoPos::new(3, 1, 0);
+        assert_eq!(None, pos.turned(&field, true));
+        assert_eq!(None, pos.turned(&field, false));
+        assert_eq!(Some(KumipuyoPos::new(3, 2, 2)), pos.quick_turned(&field));
+        assert_eq!(Some(pos), KumipuyoPos::new(3, 2, 2).quick_turned(&field));
+        assert_eq!(None, KumipuyoPos::new(3, 1, 1).quick_turned(&field));
+    }
+
+    #[test]
+    fn test_fallen() {
+        let field = PuyoPlainField::from_str("..O...");
+
+        assert_eq!(Some(KumipuyoPos::new(3, 2, 0)), KumipuyoPos::new(3, 3, 0).fallen(&field));
+        assert!(KumipuyoPos::new(3, 2, 0).is_grounded(&field));
+        assert!(KumipuyoPos::new(2, 2, 1).is_grounded(&field));
+        assert!(!KumipuyoPos::new(4, 3, 1).is_grounded(&field));
+    }
+
+    #[test]
+    fn test_apply_key_set() {
+        let field = PuyoPlainField::new();
+        let pos = KumipuyoPos::initial_pos();
+
+        assert_eq!(KumipuyoPos::new(2, 12, 1), pos.apply_key_set(&field, &KeySet::from_keys(&[Key::Left, Key::RightTurn])));
+        assert_eq!(KumipuyoPos::new(3, 11, 0), pos.apply_key_set(&field, &KeySet::from_key(Key::Down)));
+
+        // A grounded kumipuyo stays there.
+        let grounded = KumipuyoPos::new(3, 1, 0);
+        assert_eq!(grounded, grounded.apply_key_set(&field, &KeySet::from_key(Key::Down)));
+    }
 }
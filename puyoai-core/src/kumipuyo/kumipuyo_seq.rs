@@ -1,37 +1,148 @@
-use color::{Color, PuyoColor};
+use color::Color;
 use kumipuyo::Kumipuyo;
-use rand::{thread_rng, Rng};
+use kumipuyo::kumipuyo::Pair;
+use rand::{thread_rng, Rng, SeedableRng, StdRng};
 use std::vec::Vec;
 
+/// The number of colors in the official rules is 3, 4 or 5.
+/// PuyoColor has only 4 normal colors, so use RealColor for 5 colors.
+fn normal_colors<C: Color>(num_colors: usize) -> &'static [C] {
+    let colors = C::all_normal_colors();
+    assert!(3 <= num_colors && num_colors <= colors.len(), "num_colors={}", num_colors);
+    &colors[..num_colors]
+}
+
+fn make_rng(seed: usize) -> StdRng {
+    let s: &[usize] = &[seed];
+    StdRng::from_seed(s)
+}
+
 pub fn generate_random_puyocolor_sequence(size: usize) -> Vec<Kumipuyo> {
-    let mut rng = thread_rng();
+    generate_random_sequence_with_rng(4, size, &mut thread_rng())
+}
+
+/// Same as `generate_random_puyocolor_sequence`, but the same `seed` gives the same sequence.
+pub fn generate_random_puyocolor_sequence_with_seed(size: usize, seed: usize) -> Vec<Kumipuyo> {
+    generate_random_sequence_with_rng(4, size, &mut make_rng(seed))
+}
+
+/// Generates a random sequence of `num_colors` colors. The same `seed` gives the same sequence.
+pub fn generate_random_sequence_with_seed<C: Color>(num_colors: usize, size: usize, seed: usize) -> Vec<Pair<C>> {
+    generate_random_sequence_with_rng(num_colors, size, &mut make_rng(seed))
+}
+
+fn generate_random_sequence_with_rng<C: Color, R: Rng>(num_colors: usize, size: usize, rng: &mut R) -> Vec<Pair<C>> {
+    let colors = normal_colors::<C>(num_colors);
     let mut vs = Vec::new();
     for _ in 0 .. size {
-        let axis = rng.choose(PuyoColor::all_normal_colors()).unwrap();
-        let child = rng.choose(PuyoColor::all_normal_colors()).unwrap();
-        vs.push(Kumipuyo::new(*axis, *child))
+        let axis = rng.choose(colors).unwrap();
+        let child = rng.choose(colors).unwrap();
+        vs.push(Pair::new(*axis, *child))
     }
 
     vs
 }
 
 pub fn generate_ac_puyo2_sequence() -> Vec<Kumipuyo> {
-    let mut vs: Vec<PuyoColor> = Vec::new();
-    for c in &[PuyoColor::RED, PuyoColor::BLUE, PuyoColor::YELLOW, PuyoColor::GREEN] {
-        for _ in 0 .. 64 {
-            vs.push(*c)
+    generate_ac_sequence_with_rng(4, &mut thread_rng())
+}
+
+/// Same as `generate_ac_puyo2_sequence`, but the same `seed` gives the same sequence.
+pub fn generate_ac_puyo2_sequence_with_seed(seed: usize) -> Vec<Kumipuyo> {
+    generate_ac_sequence_with_rng(4, &mut make_rng(seed))
+}
+
+/// Same as `generate_ac_puyo2_sequence`, but with `num_colors` colors.
+/// The same `seed` gives the same sequence.
+pub fn generate_ac_sequence_with_seed<C: Color>(num_colors: usize, seed: usize) -> Vec<Pair<C>> {
+    generate_ac_sequence_with_rng(num_colors, &mut make_rng(seed))
+}
+
+// 256 puyos are divided into the colors as evenly as possible. The first 3 hands
+// never contain the last color. The puyos are laid out color by color before shuffled,
+// so that a seed gives the same 4 color sequence as before.
+fn generate_ac_sequence_with_rng<C: Color, R: Rng>(num_colors: usize, rng: &mut R) -> Vec<Pair<C>> {
+    let colors = normal_colors::<C>(num_colors);
+    let mut vs: Vec<C> = Vec::new();
+    for (i, c) in colors.iter().enumerate() {
+        let n = 256 / num_colors + if i < 256 % num_colors { 1 } else { 0 };
+        for _ in 0 .. n {
+            vs.push(*c);
         }
     }
+    let num_last = 256 / num_colors;
 
-    let mut rng = thread_rng();
-    rng.shuffle(&mut vs[0..64 * 3]);
-    rng.shuffle(&mut vs[6..64 * 4]);
+    rng.shuffle(&mut vs[0..256 - num_last]);
+    rng.shuffle(&mut vs[6..256]);
 
-    let mut ks: Vec<Kumipuyo> = Vec::new();
+    let mut ks: Vec<Pair<C>> = Vec::new();
     for i in 0 .. 128 {
         let axis = vs[2 * i];
         let child = vs[2 * i + 1];
-        ks.push(Kumipuyo::new(axis, child));
+        ks.push(Pair::new(axis, child));
+    }
+
+    ks
+}
+
+// The random number generator of AC puyo2.
+fn next_ac_puyo2_seed(seed: u32) -> u32 {
+    seed.wrapping_mul(0x5D588B65).wrapping_add(0x269EC3)
+}
+
+// Makes the tsumo tables of AC puyo2 for 3, 4 and 5 colors from the game seed.
+// Each table has 256 puyos, and the values are the color indices.
+fn make_ac_puyo2_tsumo_tables(game_seed: u32) -> [[usize; 256]; 3] {
+    let mut tables = [[0; 256]; 3];
+    let mut seed = game_seed;
+
+    for mode in 0 .. 3 {
+        let num_colors = mode + 3;
+        for i in 0 .. 256 {
+            tables[mode][i] = i % num_colors;
+        }
+
+        // Swaps the puyos in the neighboring blocks. The block is 16, 8, and then 4 puyos.
+        for &(shift, num_loops, block) in &[(28, 15, 16), (27, 7, 8), (26, 3, 4)] {
+            for _ in 0 .. num_loops {
+                for m in 0 .. 256 / block {
+                    seed = next_ac_puyo2_seed(seed);
+                    let a = ((seed >> shift) as usize + m * block) & 0xFF;
+                    seed = next_ac_puyo2_seed(seed);
+                    let b = ((seed >> shift) as usize + (m + 1) * block) & 0xFF;
+                    tables[mode].swap(a, b);
+                }
+            }
+        }
+    }
+
+    // The first 2 pairs are always from the 3 color table.
+    for i in 0 .. 4 {
+        tables[1][i] = tables[0][i];
+        tables[2][i] = tables[0][i];
+    }
+
+    tables
+}
+
+/// Generates the 4 color sequence that AC puyo2 gives for `game_seed`.
+/// The color indices are mapped to RED, BLUE, YELLOW and GREEN in this order.
+pub fn generate_real_ac_puyo2_sequence(game_seed: u32) -> Vec<Kumipuyo> {
+    generate_real_ac_puyo2_sequence_with_colors(4, game_seed)
+}
+
+/// Generates the sequence that AC puyo2 gives for `game_seed` with `num_colors` colors.
+/// The color indices are mapped to `C::all_normal_colors()` in this order.
+pub fn generate_real_ac_puyo2_sequence_with_colors<C: Color>(num_colors: usize, game_seed: u32) -> Vec<Pair<C>> {
+    let colors = normal_colors::<C>(num_colors);
+    let tables = make_ac_puyo2_tsumo_tables(game_seed);
+    let table = &tables[num_colors - 3];
+
+    let mut ks: Vec<Pair<C>> = Vec::new();
+    for i in 0 .. 128 {
+        let axis = colors[table[2 * i]];
+        let child = colors[table[2 * i + 1]];
+        ks.push(Pair::new(axis, child));
     }
 
     ks
@@ -39,7 +150,8 @@ pub fn generate_ac_puyo2_sequence() -> Vec<Kumipuyo> {
 
 #[cfg(test)]
 mod tests {
-    use color::{Color, PuyoColor};
+    use color::{Color, PuyoColor, RealColor};
+    use kumipuyo::kumipuyo::Pair;
 
     #[test]
     fn test_generate_random() {
@@ -70,4 +182,115 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_generate_with_seed() {
+        assert_eq!(super::generate_random_puyocolor_sequence_with_seed(20, 1),
+                   super::generate_random_puyocolor_sequence_with_seed(20, 1));
+        assert!(super::generate_random_puyocolor_sequence_with_seed(20, 1) !=
+                super::generate_random_puyocolor_sequence_with_seed(20, 2));
+
+        assert_eq!(super::generate_ac_puyo2_sequence_with_seed(1),
+                   super::generate_ac_puyo2_sequence_with_seed(1));
+        assert!(super::generate_ac_puyo2_sequence_with_seed(1) !=
+                super::generate_ac_puyo2_sequence_with_seed(2));
+
+        // A seed must keep giving the same sequence.
+        let seq = super::generate_ac_puyo2_sequence_with_seed(1);
+        assert_eq!(make_pairs::<PuyoColor>("BY YY YB BB RY GR RR YY GB YB BB RR RY GR RB GB"), &seq[0 .. 16]);
+        let seq: Vec<Pair<PuyoColor>> = super::generate_ac_sequence_with_seed(4, 2);
+        assert_eq!(make_pairs::<PuyoColor>("YY BR RY YG GG YR YB BG RB RG BY BG BY YG BB GB"), &seq[0 .. 16]);
+    }
+
+    #[test]
+    fn test_generate_real_ac_puyo2() {
+        let seq = super::generate_real_ac_puyo2_sequence(0);
+        assert_eq!(seq.len(), 128);
+        assert_eq!(seq, super::generate_real_ac_puyo2_sequence(0));
+        assert!(seq != super::generate_real_ac_puyo2_sequence(1));
+
+        for i in 0 .. 128 {
+            let ref k = seq[i];
+            assert!(k.axis().is_normal_color());
+            assert!(k.child().is_normal_color());
+
+            // The first 2 hands come from the 3 color table.
+            if i <= 1 {
+                assert!(k.axis() != PuyoColor::GREEN);
+                assert!(k.child() != PuyoColor::GREEN);
+            }
+        }
+    }
+
+    fn make_pairs<C: Color>(s: &str) -> Vec<Pair<C>> {
+        s.split(' ').map(|p| Pair::new(C::from_byte(p.as_bytes()[0]), C::from_byte(p.as_bytes()[1]))).collect()
+    }
+
+    #[test]
+    fn test_generate_real_ac_puyo2_regression() {
+        // These pairs are not from the arcade game nor a published list. They were recorded
+        // when the generator was written, and agree with a Python transliteration of
+        // make_ac_puyo2_tsumo_tables made at the same time, so they only catch changes of
+        // the output. Replace them with published sequences for known seeds if available.
+        let seq = super::generate_real_ac_puyo2_sequence(0);
+        assert_eq!(make_pairs::<PuyoColor>("BY RY YR YG GR GR GB YY BR YG BR YG GB YG GR GR"), &seq[0 .. 16]);
+
+        let seq = super::generate_real_ac_puyo2_sequence(12345);
+        assert_eq!(make_pairs::<PuyoColor>("BR BR BY BR RR RY YG RG RG RG BG RY YG BY YB GR"), &seq[0 .. 16]);
+
+        let seq: Vec<Pair<RealColor>> = super::generate_real_ac_puyo2_sequence_with_colors(5, 1);
+        assert_eq!(make_pairs::<RealColor>("BR BB RG PB PG PP YB PY GR BP RB GR YR RP BB PP"), &seq[0 .. 16]);
+
+        // The first 2 pairs are the same as the 3 color sequence.
+        let seq3: Vec<Pair<RealColor>> = super::generate_real_ac_puyo2_sequence_with_colors(3, 1);
+        assert_eq!(&seq3[0 .. 2], &seq[0 .. 2]);
+    }
+
+    #[test]
+    fn test_generate_real_ac_puyo2_color_counts() {
+        // The 3 color table is a shuffle of 0, 1, 2, 0, 1, 2, ..., so 256 puyos are
+        // 86 RED, 85 BLUE and 85 YELLOW for any seed.
+        for &seed in &[0, 1, 12345, 0xFFFFFFFF] {
+            let seq: Vec<Pair<RealColor>> = super::generate_real_ac_puyo2_sequence_with_colors(3, seed);
+            let mut counts = [0; 3];
+            for k in &seq {
+                for &c in &[k.axis(), k.child()] {
+                    counts[(c as usize) - (RealColor::RED as usize)] += 1;
+                }
+            }
+            assert_eq!([86, 85, 85], counts, "seed={}", seed);
+        }
+    }
+
+    #[test]
+    fn test_generate_with_colors() {
+        for num_colors in 3 .. 6 {
+            let seq: Vec<Pair<RealColor>> = super::generate_random_sequence_with_seed(num_colors, 100, 1);
+            assert!(seq.iter().all(|k| (k.axis() as usize) < (RealColor::RED as usize) + num_colors));
+
+            let seq: Vec<Pair<RealColor>> = super::generate_ac_sequence_with_seed(num_colors, 1);
+            assert_eq!(128, seq.len());
+            let last = RealColor::all_normal_colors()[num_colors - 1];
+            for (i, k) in seq.iter().enumerate() {
+                assert!((k.axis() as usize) < (RealColor::RED as usize) + num_colors);
+                if i <= 2 {
+                    assert!(k.axis() != last && k.child() != last);
+                }
+            }
+
+            let seq: Vec<Pair<RealColor>> = super::generate_real_ac_puyo2_sequence_with_colors(num_colors, 1);
+            assert_eq!(128, seq.len());
+            assert!(seq.iter().all(|k| (k.child() as usize) < (RealColor::RED as usize) + num_colors));
+        }
+
+        // 5 colors appear in the 5 color sequence.
+        let seq: Vec<Pair<RealColor>> = super::generate_ac_sequence_with_seed(5, 1);
+        assert!(seq.iter().any(|k| k.axis() == RealColor::PURPLE || k.child() == RealColor::PURPLE));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_generate_too_many_colors() {
+        let _: Vec<Pair<PuyoColor>> = super::generate_random_sequence_with_seed(5, 10, 1);
+    }
 }
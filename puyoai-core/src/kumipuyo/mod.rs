@@ -1,8 +1,10 @@
 pub mod kumipuyo;
+pub mod kumipuyo_moving_state;
 pub mod kumipuyo_pos;
 pub mod kumipuyo_seq;
 
 pub use self::kumipuyo::Kumipuyo;
 pub use self::kumipuyo::Kumireal;
 
+pub use self::kumipuyo_moving_state::KumipuyoMovingState;
 pub use self::kumipuyo_pos::KumipuyoPos;
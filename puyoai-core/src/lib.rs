@@ -6,6 +6,7 @@ extern crate rand;
 extern crate x86intrin;
 
 pub mod color;
+pub mod color_map;
 pub mod column_puyo;
 pub mod column_puyo_list;
 pub mod control;
@@ -18,6 +19,8 @@ pub mod field_checker;
 pub mod frame;
 pub mod kumipuyo;
 pub mod pattern;
+#[cfg(all(target_feature = "avx2", target_feature="bmi2"))]
+pub mod plan;
 pub mod position;
 pub mod probability;
 pub mod puyop;
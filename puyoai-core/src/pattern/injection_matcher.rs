@@ -33,6 +33,12 @@ impl InjectionMatcher {
         self.colors[idx] = c;
         return true;
     }
+
+    /// Returns the color matched with `v`. EMPTY is returned if nothing has matched yet.
+    pub fn color(&self, v: char) -> PuyoColor {
+        debug_assert!('A' <= v && v <= 'D', "unpexected character: {}", v);
+        self.colors[(v as usize) - ('A' as usize)]
+    }
 }
 
 #[cfg(test)]
@@ -48,6 +54,14 @@ mod tests {
         assert!(bm.match_with_char('D', PuyoColor::GREEN));
     }
 
+    #[test]
+    fn test_color() {
+        let mut bm = InjectionMatcher::new();
+        assert!(bm.match_with_char('B', PuyoColor::RED));
+        assert_eq!(PuyoColor::EMPTY, bm.color('A'));
+        assert_eq!(PuyoColor::RED, bm.color('B'));
+    }
+
     #[test]
     fn test_match_dup() {
         let mut bm = InjectionMatcher::new();
@@ -1,2 +1,7 @@
 pub mod injection_matcher;
+pub mod pattern;
 pub mod pattern_bit;
+pub mod pattern_book;
+
+pub use self::pattern::{Pattern, PatternMatchResult};
+pub use self::pattern_book::PatternBook;
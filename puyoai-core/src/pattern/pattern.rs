@@ -0,0 +1,579 @@
+use color::{Color, PuyoColor};
+use column_puyo_list::ColumnPuyoList;
+use field::{self, CoreField};
+use field_bit::FieldBit;
+use pattern::injection_matcher::InjectionMatcher;
+use pattern::pattern_bit::PatternBit;
+
+const VARS: [char; 4] = ['A', 'B', 'C', 'D'];
+
+/// Pattern is a template of a field.
+///
+/// A template is written like a field string, and each character means
+/// * 'A' - 'D': a variable. The same variable must be the same color, and
+///   different variables must be different colors.
+/// * 'a' - 'd': must not be the color of the corresponding upper case variable.
+/// * '*': any puyo, including ojama. The cell may still be empty when the pattern is
+///   matched, and `complement` fills it with a place holder.
+/// * '&': must be empty.
+/// * '.': nothing is specified.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    name: String,
+    score: f64,
+    // template[x][y] is the character at (x, y).
+    template: [[char; field::MAP_HEIGHT]; field::MAP_WIDTH],
+    // var_bits[i] is for VARS[i]. not_bit has the lower case cells.
+    var_bits: [PatternBit; 4],
+    any_bit: FieldBit,
+    empty_bit: FieldBit,
+    num_var_cells: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternMatchResult {
+    /// The pattern score multiplied by the ratio of the matched cells.
+    pub score: f64,
+    /// The number of variable cells that already have the right color.
+    pub num_matched: usize,
+    /// The number of variable cells that are still empty.
+    pub num_needed: usize,
+    /// colors[i] is the color of 'A' + i. EMPTY if the variable hasn't matched any puyo.
+    pub colors: [PuyoColor; 4],
+}
+
+impl Pattern {
+    /// Parses a template. Like `CoreField::from_str`, the last 6 characters are the 1st row.
+    pub fn parse(name: &str, score: f64, s: &str) -> Result<Pattern, String> {
+        if s.is_empty() || s.len() % 6 != 0 {
+            return Err(format!("{}: the length of the template must be a multiple of 6: {}", name, s.len()));
+        }
+        if s.len() / 6 > 13 {
+            return Err(format!("{}: the template must be at most 13 rows", name));
+        }
+
+        let mut template = [['.'; field::MAP_HEIGHT]; field::MAP_WIDTH];
+        let mut var_bits = [PatternBit::new(FieldBit::empty(), FieldBit::empty()); 4];
+        let mut any_bit = FieldBit::empty();
+        let mut empty_bit = FieldBit::empty();
+        let mut num_var_cells = 0;
+
+        for (cnt, c) in s.chars().rev().enumerate() {
+            let x = 6 - (cnt % 6);
+            let y = (cnt / 6) + 1;
+            template[x][y] = c;
+            match c {
+                'A' ... 'D' => {
+                    let i = (c as usize) - ('A' as usize);
+                    let mut var_bit = var_bits[i].var_bit();
+                    var_bit.set(x, y);
+                    var_bits[i] = PatternBit::new(var_bit, var_bits[i].not_bit());
+                    num_var_cells += 1;
+                },
+                'a' ... 'd' => {
+                    let i = (c as usize) - ('a' as usize);
+                    let mut not_bit = var_bits[i].not_bit();
+                    not_bit.set(x, y);
+                    var_bits[i] = PatternBit::new(var_bits[i].var_bit(), not_bit);
+                },
+                '*' => any_bit.set(x, y),
+                '&' => empty_bit.set(x, y),
+                '.' => {},
+                _ => return Err(format!("{}: unexpected character in the template: {}", name, c)),
+            }
+        }
+
+        Ok(Pattern {
+            name: name.to_string(),
+            score: score,
+            template: template,
+            var_bits: var_bits,
+            any_bit: any_bit,
+            empty_bit: empty_bit,
+            num_var_cells: num_var_cells,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    /// Returns the character of the template at (x, y).
+    pub fn template_char(&self, x: usize, y: usize) -> char {
+        self.template[x][y]
+    }
+
+    pub fn var_bit(&self, v: char) -> &PatternBit {
+        debug_assert!('A' <= v && v <= 'D', "unexpected character: {}", v);
+        &self.var_bits[(v as usize) - ('A' as usize)]
+    }
+
+    pub fn any_bit(&self) -> FieldBit {
+        self.any_bit
+    }
+
+    pub fn empty_bit(&self) -> FieldBit {
+        self.empty_bit
+    }
+
+    /// Returns the height of the template on column `x`. '&' and '.' are not counted.
+    pub fn height(&self, x: usize) -> usize {
+        for y in (1 .. 14).rev() {
+            match self.template[x][y] {
+                'A' ... 'D' | '*' => return y,
+                _ => {},
+            }
+        }
+        0
+    }
+
+    /// Returns the template string, which `parse` accepts. It always has 13 rows.
+    pub fn to_template_string(&self) -> String {
+        let mut s = String::new();
+        for y in (1 .. 14).rev() {
+            for x in 1 .. field::WIDTH + 1 {
+                s.push(self.template[x][y]);
+            }
+        }
+        s
+    }
+
+    /// Returns the horizontally mirrored pattern.
+    pub fn mirror(&self, name: &str) -> Pattern {
+        let mut s = String::new();
+        for y in (1 .. 14).rev() {
+            for x in (1 .. field::WIDTH + 1).rev() {
+                s.push(self.template[x][y]);
+            }
+        }
+        Pattern::parse(name, self.score, &s).unwrap()
+    }
+
+    /// Returns the pattern shifted by `dx` columns. Positive `dx` shifts to the right.
+    /// None is returned if some cell goes out of the field.
+    pub fn shift(&self, name: &str, dx: isize) -> Option<Pattern> {
+        let mut s = String::new();
+        for y in (1 .. 14).rev() {
+            for x in 1 .. (field::WIDTH as isize) + 1 {
+                let original_x = x - dx;
+                if original_x < 1 || (field::WIDTH as isize) < original_x {
+                    s.push('.');
+                } else {
+                    s.push(self.template[original_x as usize][y]);
+                }
+            }
+        }
+
+        for y in 1 .. 14 {
+            for x in 1 .. (field::WIDTH as isize) + 1 {
+                let shifted_x = x + dx;
+                if self.template[x as usize][y] != '.' && (shifted_x < 1 || (field::WIDTH as isize) < shifted_x) {
+                    return None;
+                }
+            }
+        }
+
+        Some(Pattern::parse(name, self.score, &s).unwrap())
+    }
+
+    /// Matches `cf` with the pattern. None is returned if `cf` contradicts the pattern.
+    /// Since any puyo can be put on a '*' cell, the cell never contradicts the pattern,
+    /// and an empty '*' cell is not counted in `num_needed`.
+    pub fn match_field(&self, cf: &CoreField) -> Option<PatternMatchResult> {
+        let bf = cf.field();
+        let empty_bits = bf.bits(PuyoColor::EMPTY);
+        let normal_bits = bf.normal_color_bits();
+
+        if !self.empty_bit.not_mask(empty_bits).is_empty() {
+            return None;
+        }
+
+        let mut matcher = InjectionMatcher::new();
+        let mut num_matched = 0;
+        let mut num_needed = 0;
+
+        for (i, pb) in self.var_bits.iter().enumerate() {
+            let var_bit = pb.var_bit();
+            if var_bit.is_empty() {
+                continue;
+            }
+
+            // Only normal colors and empty are allowed for a variable.
+            if !var_bit.not_mask(empty_bits | normal_bits).is_empty() {
+                return None;
+            }
+            num_needed += (var_bit & empty_bits).popcount();
+
+            for c in PuyoColor::all_normal_colors() {
+                let matched = var_bit & bf.bits(*c);
+                if matched.is_empty() {
+                    continue;
+                }
+                if !matcher.match_with_char(VARS[i], *c) {
+                    return None;
+                }
+                num_matched += matched.popcount();
+            }
+        }
+
+        let mut colors = [PuyoColor::EMPTY; 4];
+        for (i, pb) in self.var_bits.iter().enumerate() {
+            colors[i] = matcher.color(VARS[i]);
+            if colors[i] != PuyoColor::EMPTY && !(pb.not_bit() & bf.bits(colors[i])).is_empty() {
+                return None;
+            }
+        }
+
+        let score = if self.num_var_cells == 0 {
+            0.0
+        } else {
+            self.score * (num_matched as f64) / (self.num_var_cells as f64)
+        };
+
+        Some(PatternMatchResult {
+            score: score,
+            num_matched: num_matched,
+            num_needed: num_needed,
+            colors: colors,
+        })
+    }
+
+    /// Returns the puyos to add to `cf` to complete the pattern.
+    /// The color of a variable that hasn't matched any puyo yet, and a cell that only
+    /// needs some puyo, are IRON, which is a place holder in ColumnPuyoList.
+    /// None is returned if `cf` doesn't match or the pattern cannot be completed.
+    pub fn complement(&self, cf: &CoreField) -> Option<ColumnPuyoList> {
+        let result = match self.match_field(cf) {
+            Some(result) => result,
+            None => return None,
+        };
+        let cells = match self.cells_to_fill(cf) {
+            Some(cells) => cells,
+            None => return None,
+        };
+
+        let mut cpl = ColumnPuyoList::new();
+        for &(x, t) in &cells {
+            let c = match t {
+                'A' ... 'D' => result.colors[(t as usize) - ('A' as usize)],
+                _ => PuyoColor::EMPTY,
+            };
+            if !cpl.add(x, if c == PuyoColor::EMPTY { PuyoColor::IRON } else { c }) {
+                return None;
+            }
+        }
+
+        Some(cpl)
+    }
+
+    /// Calls `callback` with every way to complete the pattern on `cf`.
+    ///
+    /// A variable that hasn't matched any puyo yet gets every color that keeps the
+    /// variables injective. A lower case cell that has to be filled gets every color
+    /// except the color of its variable. A cell that only needs some puyo ('*' or '.')
+    /// is IRON, which is a place holder in ColumnPuyoList. '&' cells are never filled.
+    /// `callback` also receives the colors of the variables for the answer.
+    pub fn iterate_complements<F>(&self, cf: &CoreField, mut callback: F)
+        where F: FnMut(&ColumnPuyoList, &[PuyoColor; 4]) {
+        let result = match self.match_field(cf) {
+            Some(result) => result,
+            None => return,
+        };
+        let cells = match self.cells_to_fill(cf) {
+            Some(cells) => cells,
+            None => return,
+        };
+
+        // Variables that need colors.
+        let mut unknown_vars = Vec::new();
+        for i in 0 .. 4 {
+            if result.colors[i] == PuyoColor::EMPTY && cells.iter().any(|&(_, t)| t == VARS[i]) {
+                unknown_vars.push(i);
+            }
+        }
+
+        let mut colors = result.colors;
+        self.iterate_var_colors(cf, &cells, &unknown_vars, &mut colors, &mut callback);
+    }
+
+    fn iterate_var_colors<F>(&self, cf: &CoreField, cells: &[(usize, char)], unknown_vars: &[usize],
+                             colors: &mut [PuyoColor; 4], callback: &mut F)
+        where F: FnMut(&ColumnPuyoList, &[PuyoColor; 4]) {
+        if unknown_vars.is_empty() {
+            let mut cell_colors = Vec::with_capacity(cells.len());
+            self.iterate_cell_colors(cells, colors, &mut cell_colors, callback);
+            return;
+        }
+
+        let i = unknown_vars[0];
+        for c in PuyoColor::all_normal_colors() {
+            // The color must not be used by another variable, nor by the puyos on its not_bit.
+            if colors.contains(c) || !(self.var_bits[i].not_bit() & cf.field().bits(*c)).is_empty() {
+                continue;
+            }
+            colors[i] = *c;
+            self.iterate_var_colors(cf, cells, &unknown_vars[1..], colors, callback);
+            colors[i] = PuyoColor::EMPTY;
+        }
+    }
+
+    fn iterate_cell_colors<F>(&self, cells: &[(usize, char)], colors: &[PuyoColor; 4],
+                              cell_colors: &mut Vec<PuyoColor>, callback: &mut F)
+        where F: FnMut(&ColumnPuyoList, &[PuyoColor; 4]) {
+        if cell_colors.len() == cells.len() {
+            let mut cpl = ColumnPuyoList::new();
+            for (&(x, _), c) in cells.iter().zip(cell_colors.iter()) {
+                if !cpl.add(x, *c) {
+                    return;
+                }
+            }
+            callback(&cpl, colors);
+            return;
+        }
+
+        let (_, t) = cells[cell_colors.len()];
+        match t {
+            'A' ... 'D' => {
+                cell_colors.push(colors[(t as usize) - ('A' as usize)]);
+                self.iterate_cell_colors(cells, colors, cell_colors, callback);
+                cell_colors.pop();
+            },
+            'a' ... 'd' => {
+                let forbidden = colors[(t as usize) - ('a' as usize)];
+                if forbidden == PuyoColor::EMPTY {
+                    cell_colors.push(PuyoColor::IRON);
+                    self.iterate_cell_colors(cells, colors, cell_colors, callback);
+                    cell_colors.pop();
+                    return;
+                }
+                for c in PuyoColor::all_normal_colors() {
+                    if *c == forbidden {
+                        continue;
+                    }
+                    cell_colors.push(*c);
+                    self.iterate_cell_colors(cells, colors, cell_colors, callback);
+                    cell_colors.pop();
+                }
+            },
+            _ => {
+                cell_colors.push(PuyoColor::IRON);
+                self.iterate_cell_colors(cells, colors, cell_colors, callback);
+                cell_colors.pop();
+            },
+        }
+    }
+
+    // Returns the cells to fill to complete the pattern, from the bottom of each column.
+    // None is returned if a '&' cell has to be filled.
+    fn cells_to_fill(&self, cf: &CoreField) -> Option<Vec<(usize, char)>> {
+        let mut cells = Vec::new();
+        for x in 1 .. field::WIDTH + 1 {
+            for y in (cf.height(x) + 1) .. (self.height(x) + 1) {
+                let t = self.template[x][y];
+                if t == '&' {
+                    return None;
+                }
+                cells.push((x, t));
+            }
+        }
+
+        Some(cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pattern;
+    use color::PuyoColor;
+    use field::CoreField;
+
+    fn gtr() -> Pattern {
+        Pattern::parse("GTR", 1000.0, concat!(
+            "&&....",
+            "BCD...",
+            "AAB...",
+            "ABB...")).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let pattern = gtr();
+        assert_eq!("GTR", pattern.name());
+        assert_eq!('A', pattern.template_char(1, 1));
+        assert_eq!('D', pattern.template_char(3, 3));
+        assert_eq!('&', pattern.template_char(1, 4));
+        assert_eq!(3, pattern.height(1));
+        assert_eq!(0, pattern.height(4));
+        assert_eq!(3, pattern.var_bit('A').var_bit().popcount());
+        assert_eq!(2, pattern.empty_bit().popcount());
+
+        assert!(Pattern::parse("bad", 0.0, "ABC").is_err());
+        assert!(Pattern::parse("bad", 0.0, "ABCDEF").is_err());
+        assert!(Pattern::parse("bad", 0.0, &"A.....".repeat(14)).is_err());
+    }
+
+    #[test]
+    fn test_mirror_and_shift() {
+        let pattern = Pattern::parse("p", 1.0, concat!(
+            "&.....",
+            "AB....")).unwrap();
+
+        let mirrored = pattern.mirror("mirrored");
+        assert_eq!("mirrored", mirrored.name());
+        assert_eq!('A', mirrored.template_char(6, 1));
+        assert_eq!('B', mirrored.template_char(5, 1));
+        assert_eq!('&', mirrored.template_char(6, 2));
+
+        let shifted = pattern.shift("shifted", 4).unwrap();
+        assert_eq!('A', shifted.template_char(5, 1));
+        assert_eq!('B', shifted.template_char(6, 1));
+        assert_eq!('.', shifted.template_char(1, 1));
+
+        assert!(pattern.shift("shifted", 5).is_none());
+        assert!(pattern.shift("shifted", -1).is_none());
+        assert!(mirrored.shift("shifted", -4).is_some());
+
+        assert_eq!(pattern.to_template_string(),
+                   Pattern::parse("p", 1.0, &pattern.to_template_string()).unwrap().to_template_string());
+    }
+
+    #[test]
+    fn test_match_field() {
+        let pattern = gtr();
+
+        let result = pattern.match_field(&CoreField::new()).unwrap();
+        assert_eq!(0, result.num_matched);
+        assert_eq!(9, result.num_needed);
+        assert_eq!(0.0, result.score);
+
+        let cf = CoreField::from_str(concat!(
+            "RRB...",
+            "RBB..."));
+        let result = pattern.match_field(&cf).unwrap();
+        assert_eq!(6, result.num_matched);
+        assert_eq!(3, result.num_needed);
+        assert_eq!([PuyoColor::RED, PuyoColor::BLUE, PuyoColor::EMPTY, PuyoColor::EMPTY], result.colors);
+        assert_eq!(1000.0 * 6.0 / 9.0, result.score);
+    }
+
+    #[test]
+    fn test_match_field_failure() {
+        let pattern = gtr();
+
+        // A and B are the same color.
+        assert!(pattern.match_field(&CoreField::from_str("RR....")).is_none());
+        // A has two colors.
+        assert!(pattern.match_field(&CoreField::from_str(concat!("Y.....", "R....."))).is_none());
+        // Ojama on a variable.
+        assert!(pattern.match_field(&CoreField::from_str("O.....")).is_none());
+        // '&' must be empty.
+        assert!(pattern.match_field(&CoreField::from_str(concat!(
+            "G.....",
+            "B.....",
+            "R.....",
+            "R....."))).is_none());
+    }
+
+    #[test]
+    fn test_match_field_any() {
+        let pattern = Pattern::parse("any", 1.0, concat!(
+            "*.....",
+            "A.....")).unwrap();
+
+        // '*' accepts an empty cell, a normal color and ojama.
+        let result = pattern.match_field(&CoreField::from_str("R.....")).unwrap();
+        assert_eq!(1, result.num_matched);
+        assert_eq!(0, result.num_needed);
+        assert!(pattern.match_field(&CoreField::from_str(concat!("B.....", "R....."))).is_some());
+        assert!(pattern.match_field(&CoreField::from_str(concat!("O.....", "R....."))).is_some());
+
+        // The empty '*' cell is completed with a place holder.
+        let cpl = pattern.complement(&CoreField::from_str("R.....")).unwrap();
+        assert_eq!(1, cpl.size_on(1));
+        assert_eq!(Some(PuyoColor::IRON), cpl.top(1));
+    }
+
+    #[test]
+    fn test_match_field_not_bit() {
+        let pattern = Pattern::parse("not", 1.0, "Aa....").unwrap();
+        assert!(pattern.match_field(&CoreField::from_str("RB....")).is_some());
+        assert!(pattern.match_field(&CoreField::from_str("RR....")).is_none());
+        assert!(pattern.match_field(&CoreField::from_str(".R....")).is_some());
+    }
+
+    #[test]
+    fn test_complement() {
+        let pattern = gtr();
+        let cf = CoreField::from_str(concat!(
+            "RRB...",
+            "RBB..."));
+
+        let cpl = pattern.complement(&cf).unwrap();
+        assert_eq!(1, cpl.size_on(1));
+        assert_eq!(Some(PuyoColor::BLUE), cpl.top(1));
+        assert_eq!(1, cpl.size_on(2));
+        assert_eq!(Some(PuyoColor::IRON), cpl.top(2));
+        assert_eq!(1, cpl.size_on(3));
+        assert_eq!(Some(PuyoColor::IRON), cpl.top(3));
+        assert!(cpl.has_place_holder());
+
+        assert!(pattern.complement(&CoreField::from_str("RR....")).is_none());
+    }
+
+    #[test]
+    fn test_iterate_complements() {
+        let pattern = Pattern::parse("p", 1.0, concat!(
+            ".*....",
+            "Cb....",
+            "AB....")).unwrap();
+
+        // A is RED and B is BLUE. C can be YELLOW or GREEN, and b can be RED, YELLOW or GREEN.
+        let cf = CoreField::from_str("RB....");
+        let mut count = 0;
+        pattern.iterate_complements(&cf, |cpl, colors| {
+            assert_eq!(PuyoColor::RED, colors[0]);
+            assert_eq!(PuyoColor::BLUE, colors[1]);
+            assert!(colors[2] == PuyoColor::YELLOW || colors[2] == PuyoColor::GREEN);
+            assert_eq!(Some(colors[2]), cpl.top(1));
+            assert_eq!(2, cpl.size_on(2));
+            assert!(cpl.get(2, 0) != PuyoColor::BLUE);
+            assert_eq!(PuyoColor::IRON, cpl.get(2, 1));
+            count += 1;
+        });
+        assert_eq!(2 * 3, count);
+    }
+
+    #[test]
+    fn test_iterate_complements_with_place_holder() {
+        let pattern = Pattern::parse("p", 1.0, concat!(
+            "A.....",
+            "*.....",
+            "A.....")).unwrap();
+
+        let cf = CoreField::from_str("R.....");
+        let mut count = 0;
+        pattern.iterate_complements(&cf, |cpl, _| {
+            assert_eq!(2, cpl.size_on(1));
+            assert_eq!(PuyoColor::IRON, cpl.get(1, 0));
+            assert_eq!(PuyoColor::RED, cpl.get(1, 1));
+            count += 1;
+        });
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn test_iterate_complements_empty_cell() {
+        // '&' must stay empty, so A on it cannot be completed.
+        let pattern = Pattern::parse("p", 1.0, concat!(
+            "A.....",
+            "&.....")).unwrap();
+
+        let mut count = 0;
+        pattern.iterate_complements(&CoreField::new(), |_, _| count += 1);
+        assert_eq!(0, count);
+    }
+}
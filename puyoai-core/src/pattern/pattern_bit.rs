@@ -1,5 +1,6 @@
 use field_bit::FieldBit;
 
+#[derive(Clone, Copy, Debug)]
 pub struct PatternBit {
     var_bit: FieldBit,
     not_bit: FieldBit,
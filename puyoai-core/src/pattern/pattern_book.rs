@@ -0,0 +1,363 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use color::PuyoColor;
+use column_puyo_list::ColumnPuyoList;
+use field::CoreField;
+use pattern::pattern::{Pattern, PatternMatchResult};
+
+/// PatternBook is a collection of patterns.
+///
+/// A pattern book file is a list of entries separated by blank lines.
+/// Lines starting with '#' are comments.
+///
+/// ```text
+/// name: GTR
+/// score: 1000
+/// mirror: true
+/// allow-shift: false
+/// field:
+/// BCD...
+/// AAB...
+/// ABB...
+/// ```
+///
+/// `field:` must be the last key of an entry. When `mirror` is true, the horizontally
+/// mirrored pattern is added. When `allow-shift` is true, the patterns shifted
+/// horizontally are added as long as they are inside the field.
+pub struct PatternBook {
+    patterns: Vec<Pattern>,
+}
+
+impl PatternBook {
+    pub fn new() -> PatternBook {
+        PatternBook {
+            patterns: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, pattern: Pattern) {
+        self.patterns.push(pattern);
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<PatternBook, String> {
+        let mut s = String::new();
+        try!(File::open(path.as_ref())
+             .and_then(|mut f| f.read_to_string(&mut s))
+             .map_err(|e| format!("failed to read {}: {}", path.as_ref().display(), e)));
+        PatternBook::parse(&s)
+    }
+
+    pub fn parse(s: &str) -> Result<PatternBook, String> {
+        let mut book = PatternBook::new();
+        let mut entry = PatternEntry::new();
+        let mut in_field = false;
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.is_empty() {
+                if !entry.is_empty() {
+                    try!(entry.expand_into(&mut book));
+                }
+                entry = PatternEntry::new();
+                in_field = false;
+                continue;
+            }
+
+            if entry.line_no == 0 {
+                entry.line_no = i + 1;
+            }
+            if in_field {
+                entry.rows.push_str(line);
+                continue;
+            }
+
+            let colon = match line.find(':') {
+                Some(colon) => colon,
+                None => return Err(format!("line {}: ':' is missing: {}", i + 1, line)),
+            };
+            let (key, value) = (line[..colon].trim(), line[colon + 1..].trim());
+            match key {
+                "name" => entry.name = Some(value.to_string()),
+                "score" => {
+                    entry.score = try!(value.parse::<f64>()
+                                       .map_err(|_| format!("line {}: invalid score: {}", i + 1, value)));
+                },
+                "mirror" => entry.mirror = try!(parse_bool(i + 1, value)),
+                "allow-shift" => entry.allow_shift = try!(parse_bool(i + 1, value)),
+                "field" => in_field = true,
+                _ => return Err(format!("line {}: unknown key: {}", i + 1, key)),
+            }
+        }
+
+        if !entry.is_empty() {
+            try!(entry.expand_into(&mut book));
+        }
+
+        Ok(book)
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Calls `callback` with every pattern that `cf` matches with at least one puyo.
+    pub fn iterate_match<F: FnMut(&Pattern, &PatternMatchResult)>(&self, cf: &CoreField, mut callback: F) {
+        for pattern in &self.patterns {
+            if let Some(result) = pattern.match_field(cf) {
+                if result.num_matched > 0 {
+                    callback(pattern, &result);
+                }
+            }
+        }
+    }
+
+    /// Calls `callback` with every way to complete every pattern that `cf` matches with
+    /// at least one puyo. See `Pattern::iterate_complements`.
+    pub fn iterate_complements<F>(&self, cf: &CoreField, mut callback: F)
+        where F: FnMut(&Pattern, &ColumnPuyoList, &[PuyoColor; 4]) {
+        for pattern in &self.patterns {
+            match pattern.match_field(cf) {
+                Some(ref result) if result.num_matched > 0 => {},
+                _ => continue,
+            }
+            pattern.iterate_complements(cf, |cpl, colors| callback(pattern, cpl, colors));
+        }
+    }
+
+    /// Returns the pattern with the best score and its result.
+    pub fn best_match(&self, cf: &CoreField) -> Option<(&Pattern, PatternMatchResult)> {
+        let mut best: Option<(&Pattern, PatternMatchResult)> = None;
+        for pattern in &self.patterns {
+            let result = match pattern.match_field(cf) {
+                Some(result) => result,
+                None => continue,
+            };
+            if result.num_matched == 0 {
+                continue;
+            }
+            let is_better = match best {
+                Some((_, ref best_result)) => best_result.score < result.score,
+                None => true,
+            };
+            if is_better {
+                best = Some((pattern, result));
+            }
+        }
+
+        best
+    }
+}
+
+fn parse_bool(line_no: usize, value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("line {}: invalid bool: {}", line_no, value)),
+    }
+}
+
+// PatternEntry is an entry in a pattern book file.
+struct PatternEntry {
+    // The line number where the entry starts. Errors in the entry are reported with this.
+    line_no: usize,
+    name: Option<String>,
+    score: f64,
+    mirror: bool,
+    allow_shift: bool,
+    rows: String,
+}
+
+impl PatternEntry {
+    fn new() -> PatternEntry {
+        PatternEntry {
+            line_no: 0,
+            name: None,
+            score: 0.0,
+            mirror: false,
+            allow_shift: false,
+            rows: String::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.name.is_none() && self.rows.is_empty()
+    }
+
+    // Adds the pattern and its variants to `book`. Duplicated variants are added only once.
+    fn expand_into(&self, book: &mut PatternBook) -> Result<(), String> {
+        let name = match self.name {
+            Some(ref name) => name.clone(),
+            None => return Err(format!("line {}: name is missing", self.line_no)),
+        };
+
+        let pattern = try!(Pattern::parse(&name, self.score, &self.rows)
+                           .map_err(|e| format!("line {}: {}", self.line_no, e)));
+        let mut variants = vec![pattern];
+        if self.mirror {
+            let mirrored = variants[0].mirror(&format!("{} (mirror)", name));
+            variants.push(mirrored);
+        }
+        if self.allow_shift {
+            let mut shifted = Vec::new();
+            for variant in &variants {
+                for dx in -5isize .. 6 {
+                    if dx == 0 {
+                        continue;
+                    }
+                    let shifted_name = format!("{} (shift {:+})", variant.name(), dx);
+                    if let Some(p) = variant.shift(&shifted_name, dx) {
+                        shifted.push(p);
+                    }
+                }
+            }
+            variants.extend(shifted);
+        }
+
+        let mut seen = HashSet::new();
+        for variant in variants {
+            if seen.insert(variant.to_template_string()) {
+                book.add(variant);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PatternBook;
+    use field::CoreField;
+    use pattern::pattern::Pattern;
+
+    fn make_book() -> PatternBook {
+        let mut book = PatternBook::new();
+        book.add(Pattern::parse("GTR", 1000.0, concat!(
+            "BCD...",
+            "AAB...",
+            "ABB...")).unwrap());
+        book.add(Pattern::parse("STAIRS", 800.0, concat!(
+            "..C...",
+            ".BC...",
+            "ABC...",
+            "AAB...")).unwrap());
+        book
+    }
+
+    #[test]
+    fn test_iterate_match() {
+        let book = make_book();
+        assert_eq!(2, book.len());
+
+        let cf = CoreField::from_str("RBB...");
+        let mut names = Vec::new();
+        book.iterate_match(&cf, |pattern, _| names.push(pattern.name().to_string()));
+        assert_eq!(vec!["GTR".to_string()], names);
+
+        let mut count = 0;
+        book.iterate_match(&CoreField::new(), |_, _| count += 1);
+        assert_eq!(0, count);
+    }
+
+    #[test]
+    fn test_iterate_complements() {
+        let book = make_book();
+
+        // GTR matches with A=RED and B=BLUE. C and D take YELLOW and GREEN in either order.
+        let cf = CoreField::from_str("RBB...");
+        let mut count = 0;
+        book.iterate_complements(&cf, |pattern, cpl, colors| {
+            assert_eq!("GTR", pattern.name());
+            assert_eq!(6, cpl.size());
+            assert_eq!(Some(colors[2]), cpl.top(2));
+            count += 1;
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn test_parse() {
+        let book = PatternBook::parse(concat!(
+            "# comment\n",
+            "name: GTR\n",
+            "score: 1000\n",
+            "field:\n",
+            "BCD...\n",
+            "AAB...\n",
+            "ABB...\n",
+            "\n",
+            "name: COLUMN\n",
+            "score: 10\n",
+            "mirror: true\n",
+            "allow-shift: true\n",
+            "field:\n",
+            "A.....\n",
+            "A.....\n")).unwrap();
+
+        // GTR, and 6 columns for COLUMN. The mirrored ones are the same as the shifted ones.
+        assert_eq!(1 + 6, book.len());
+        assert_eq!("GTR", book.patterns()[0].name());
+        assert_eq!(1000.0, book.patterns()[0].score());
+        assert_eq!("COLUMN", book.patterns()[1].name());
+        assert_eq!("COLUMN (mirror)", book.patterns()[2].name());
+        assert_eq!(10.0, book.patterns()[6].score());
+    }
+
+    #[test]
+    fn test_parse_mirror_without_shift() {
+        let book = PatternBook::parse(concat!(
+            "name: GTR\n",
+            "score: 1000\n",
+            "mirror: true\n",
+            "field:\n",
+            "BCD...\n",
+            "AAB...\n",
+            "ABB...\n")).unwrap();
+
+        assert_eq!(2, book.len());
+        assert_eq!('A', book.patterns()[1].template_char(6, 1));
+        assert_eq!('B', book.patterns()[1].template_char(4, 3));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(PatternBook::parse("name GTR").is_err());
+        assert!(PatternBook::parse("name: GTR\nscore: x").is_err());
+        assert!(PatternBook::parse("name: GTR\nmirror: yes").is_err());
+        assert!(PatternBook::parse("name: GTR\nfoo: bar").is_err());
+        assert!(PatternBook::parse("score: 10\nfield:\nA.....").is_err());
+        assert!(PatternBook::parse("name: GTR\nfield:\nA....").is_err());
+
+        // Errors in an entry are reported with the line where the entry starts.
+        assert_eq!("line 3: name is missing",
+                   PatternBook::parse("# comment\n\nscore: 10\nfield:\nA.....").err().unwrap());
+        let e = PatternBook::parse("name: A\nfield:\nA.....\n\nname: B\nfield:\nA....").err().unwrap();
+        assert!(e.starts_with("line 5: "), "{}", e);
+    }
+
+    #[test]
+    fn test_best_match() {
+        let book = make_book();
+
+        let cf = CoreField::from_str("RRB...");
+        let (pattern, result) = book.best_match(&cf).unwrap();
+        assert_eq!("STAIRS", pattern.name());
+        assert_eq!(3, result.num_matched);
+
+        assert!(book.best_match(&CoreField::new()).is_none());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use control::PuyoController;
+use decision::Decision;
+use field::CoreField;
+use kumipuyo::Kumipuyo;
+use rensa_result::RensaResult;
+
+/// Plan is a sequence of decisions and the field after the kumipuyos are put.
+#[derive(Clone, Debug)]
+pub struct Plan {
+    field: CoreField,
+    decisions: Vec<Decision>,
+    rensa_result: RensaResult,
+    total_frames: usize,
+}
+
+impl Plan {
+    /// Returns the field after all the kumipuyos are put and the rensa has finished.
+    pub fn field(&self) -> &CoreField {
+        &self.field
+    }
+
+    pub fn decisions(&self) -> &[Decision] {
+        &self.decisions
+    }
+
+    pub fn first_decision(&self) -> &Decision {
+        &self.decisions[0]
+    }
+
+    /// Returns the rensa caused by the last kumipuyo.
+    pub fn rensa_result(&self) -> &RensaResult {
+        &self.rensa_result
+    }
+
+    /// Returns the frames to put all the kumipuyos, including the rensa on the way.
+    pub fn total_frames(&self) -> usize {
+        self.total_frames
+    }
+
+    pub fn is_rensa_plan(&self) -> bool {
+        self.rensa_result.chain > 0
+    }
+}
+
+/// Calls `callback` with every plan that puts `depth` kumipuyos of `seq` on `field`.
+/// Unreachable decisions and decisions that make the player dead are skipped.
+pub fn iterate_available_plans<F: FnMut(&Plan)>(field: &CoreField, seq: &[Kumipuyo], depth: usize, callback: &mut F) {
+    debug_assert!(1 <= depth && depth <= 3, "depth={}", depth);
+    debug_assert!(depth <= seq.len(), "depth={} seq.len()={}", depth, seq.len());
+
+    let mut reachables = ReachableDecisions::new();
+    let mut decisions = Vec::with_capacity(depth);
+    iterate(&mut reachables, field, seq, depth, 0, &mut decisions, callback);
+}
+
+// ReachableDecisions caches the reachable decisions for each column heights.
+// A field in a plan has no floating puyo, so the heights decide where the kumipuyo can move,
+// and the search runs once for the fields that have the same heights.
+struct ReachableDecisions {
+    controller: PuyoController,
+    cache: HashMap<[usize; 6], Vec<Decision>>,
+}
+
+impl ReachableDecisions {
+    fn new() -> ReachableDecisions {
+        ReachableDecisions {
+            controller: PuyoController::new(),
+            cache: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, field: &CoreField) -> &[Decision] {
+        let mut heights = [0; 6];
+        for x in 1..7 {
+            heights[x - 1] = field.height(x);
+        }
+
+        let controller = &self.controller;
+        self.cache.entry(heights).or_insert_with(|| controller.reachable_decisions(field))
+    }
+}
+
+fn iterate<F: FnMut(&Plan)>(reachables: &mut ReachableDecisions, field: &CoreField, seq: &[Kumipuyo], depth: usize,
+                            total_frames: usize, decisions: &mut Vec<Decision>, callback: &mut F) {
+    let kumipuyo = &seq[decisions.len()];
+    let candidates = if kumipuyo.is_rep() {
+        Decision::all_valid_decisions_for_rep()
+    } else {
+        Decision::all_valid_decisions()
+    };
+
+    let reachable_decisions = reachables.get(field).to_vec();
+    for decision in candidates {
+        if !reachable_decisions.contains(decision) {
+            continue;
+        }
+
+        let drop_frames = field.frames_to_drop_next(decision);
+        let mut next_field = field.clone();
+        if !next_field.drop_kumipuyo(decision, kumipuyo) {
+            continue;
+        }
+
+        let rensa_result = next_field.simulate();
+        if !next_field.is_empty(3, 12) {
+            continue;
+        }
+
+        let frames = total_frames + drop_frames + rensa_result.frame;
+        decisions.push(decision.clone());
+        if decisions.len() == depth {
+            callback(&Plan {
+                field: next_field,
+                decisions: decisions.clone(),
+                rensa_result: rensa_result,
+                total_frames: frames,
+            });
+        } else {
+            iterate(reachables, &next_field, seq, depth, frames, decisions, callback);
+        }
+        decisions.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::iterate_available_plans;
+    use color::PuyoColor;
+    use decision::Decision;
+    use field::CoreField;
+    use kumipuyo::Kumipuyo;
+
+    #[test]
+    fn test_iterate_available_plans_1() {
+        let field = CoreField::new();
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE)];
+
+        let mut count = 0;
+        iterate_available_plans(&field, &seq, 1, &mut |plan| {
+            assert_eq!(1, plan.decisions().len());
+            assert!(!plan.is_rensa_plan());
+            assert!(plan.total_frames() > 0);
+            count += 1;
+        });
+        assert_eq!(22, count);
+    }
+
+    #[test]
+    fn test_iterate_available_plans_rep() {
+        let field = CoreField::new();
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::RED)];
+
+        let mut count = 0;
+        iterate_available_plans(&field, &seq, 1, &mut |_| count += 1);
+        assert_eq!(11, count);
+    }
+
+    #[test]
+    fn test_iterate_available_plans_2() {
+        let field = CoreField::new();
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+                       Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::YELLOW)];
+
+        let mut count = 0;
+        iterate_available_plans(&field, &seq, 2, &mut |plan| {
+            assert_eq!(2, plan.decisions().len());
+            count += 1;
+        });
+        assert_eq!(22 * 11, count);
+    }
+
+    #[test]
+    fn test_iterate_available_plans_rensa() {
+        let field = CoreField::from_str(concat!(
+            "BB....",
+            "RR...."));
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::RED),
+                       Kumipuyo::new(PuyoColor::BLUE, PuyoColor::BLUE)];
+
+        let mut found = false;
+        iterate_available_plans(&field, &seq, 2, &mut |plan| {
+            if plan.decisions() == &[Decision::new(3, 2), Decision::new(3, 2)] {
+                // RR vanishes first, and then BB vanishes with the 2nd kumipuyo.
+                assert_eq!(1, plan.rensa_result().chain);
+                assert_eq!(CoreField::new(), *plan.field());
+                found = true;
+            }
+        });
+        assert!(found);
+    }
+
+    #[test]
+    fn test_iterate_available_plans_unreachable() {
+        // The 4th column is filled up to the 13th row, so nothing can go over it.
+        let field = CoreField::from_str(&"...O..".repeat(13));
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+                       Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::GREEN)];
+
+        let mut count = 0;
+        iterate_available_plans(&field, &seq, 2, &mut |plan| {
+            for d in plan.decisions() {
+                assert!(d.axis_x() <= 4, "d={:?}", d);
+            }
+            count += 1;
+        });
+        assert!(count > 0);
+    }
+}
@@ -1 +1,2 @@
+pub mod puyo_possibility;
 pub mod puyo_set;
@@ -0,0 +1,439 @@
+use std::collections::HashMap;
+use std::f64;
+
+use kumipuyo::Kumipuyo;
+use probability::puyo_set::PuyoSet;
+
+/// The tables have the answers for PuyoSets whose counts are at most this.
+/// The answers for larger PuyoSets are calculated on demand.
+pub const MAX_TABLE_COUNT: usize = 6;
+
+const TABLE_DIM: usize = MAX_TABLE_COUNT + 1;
+// The precomputed uniform table is only for PuyoSets without `any`.
+const UNIFORM_TABLE_DIMS: [usize; 5] = [TABLE_DIM, TABLE_DIM, TABLE_DIM, TABLE_DIM, 1];
+
+// An AC sequence is a shuffled bag of 256 puyos, 64 puyos for each color.
+const AC_BAG_SIZE: usize = 256;
+const AC_BAG_COLOR_SIZE: usize = 64;
+
+/// SequenceKind is how a puyo sequence is generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SequenceKind {
+    /// Each puyo is one of the 4 colors at random.
+    Uniform,
+    /// The puyos are drawn from the top of a shuffled bag of 256 puyos.
+    AcBag,
+}
+
+/// PuyoPossibility answers how likely the puyos in a PuyoSet are supplied.
+pub struct PuyoPossibility {
+    // binomials[n][k] is C(n, k).
+    binomials: Vec<Vec<f64>>,
+    uniform_expected_pairs: Vec<f64>,
+    ac_expected_pairs: Vec<f64>,
+}
+
+impl PuyoPossibility {
+    /// Creates PuyoPossibility with the precomputed tables.
+    pub fn new() -> PuyoPossibility {
+        let binomials = make_binomials(AC_BAG_SIZE);
+
+        let uniform_expected_pairs = uniform_expected_pairs_table(&UNIFORM_TABLE_DIMS);
+
+        let mut ac_expected_pairs = vec![0.0; TABLE_DIM * TABLE_DIM * TABLE_DIM * TABLE_DIM];
+        // The answer doesn't depend on the order of the colors.
+        let mut cache: HashMap<[usize; 4], f64> = HashMap::new();
+        for r in 0 .. TABLE_DIM {
+            for b in 0 .. TABLE_DIM {
+                for y in 0 .. TABLE_DIM {
+                    for g in 0 .. TABLE_DIM {
+                        let mut key = [r, b, y, g];
+                        key.sort();
+                        let value = *cache.entry(key).or_insert_with(|| ac_expected_pairs_of(&binomials, &key, 0));
+                        ac_expected_pairs[table_index(&[r, b, y, g, 0], &UNIFORM_TABLE_DIMS)] = value;
+                    }
+                }
+            }
+        }
+
+        PuyoPossibility {
+            binomials: binomials,
+            uniform_expected_pairs: uniform_expected_pairs,
+            ac_expected_pairs: ac_expected_pairs,
+        }
+    }
+
+    /// Returns the expected number of pairs until all the puyos in `ps` appear.
+    /// `ps.any()` puyos of any color are needed besides the puyos of each color.
+    /// INFINITY is returned if they never appear.
+    pub fn expected_pairs(&self, kind: SequenceKind, ps: &PuyoSet) -> f64 {
+        let counts = needed_counts(ps);
+        let in_table = ps.any() == 0 && ps.to_array().iter().all(|&n| n <= MAX_TABLE_COUNT);
+
+        match kind {
+            SequenceKind::Uniform => {
+                if in_table {
+                    return self.uniform_expected_pairs[table_index(&counts, &UNIFORM_TABLE_DIMS)];
+                }
+                let dims = dims_of(&counts);
+                uniform_expected_pairs_table(&dims)[table_index(&counts, &dims)]
+            },
+            SequenceKind::AcBag => {
+                if in_table {
+                    return self.ac_expected_pairs[table_index(&counts, &UNIFORM_TABLE_DIMS)];
+                }
+                ac_expected_pairs_of(&self.binomials, &ps.to_array(), ps.any())
+            },
+        }
+    }
+
+    /// Returns the expected number of turns until all the puyos in `ps` are supplied.
+    /// The known pairs in `seq` (e.g. the current pair and NEXT) are used first, and then
+    /// the rest is estimated with the expected pairs.
+    pub fn expected_turns(&self, kind: SequenceKind, ps: &PuyoSet, seq: &[Kumipuyo]) -> f64 {
+        let mut rest = *ps;
+        for (i, kumipuyo) in seq.iter().enumerate() {
+            if rest.is_empty() {
+                return i as f64;
+            }
+            rest = rest.sub(&PuyoSet::from_colors(&[kumipuyo.axis(), kumipuyo.child()]));
+        }
+
+        seq.len() as f64 + self.expected_pairs(kind, &rest)
+    }
+
+    /// Returns the probability that all the puyos in `ps` appear in the first `num_pairs` pairs.
+    pub fn probability(&self, kind: SequenceKind, ps: &PuyoSet, num_pairs: usize) -> f64 {
+        match kind {
+            SequenceKind::Uniform => uniform_probability(&needed_counts(ps), num_pairs),
+            SequenceKind::AcBag => ac_probability(&self.binomials, &ps.to_array(), ps.any(), num_pairs * 2),
+        }
+    }
+}
+
+// The needed counts of red, blue, yellow, green and any.
+fn needed_counts(ps: &PuyoSet) -> [usize; 5] {
+    let counts = ps.to_array();
+    [counts[0], counts[1], counts[2], counts[3], ps.any()]
+}
+
+fn dims_of(counts: &[usize; 5]) -> [usize; 5] {
+    [counts[0] + 1, counts[1] + 1, counts[2] + 1, counts[3] + 1, counts[4] + 1]
+}
+
+fn table_index(counts: &[usize; 5], dims: &[usize; 5]) -> usize {
+    let mut index = 0;
+    for i in 0 .. 5 {
+        index = index * dims[i] + counts[i];
+    }
+    index
+}
+
+// The inverse of table_index.
+fn table_counts(index: usize, dims: &[usize; 5]) -> [usize; 5] {
+    let mut counts = [0; 5];
+    let mut rest = index;
+    for i in (0 .. 5).rev() {
+        counts[i] = rest % dims[i];
+        rest /= dims[i];
+    }
+    counts
+}
+
+fn make_binomials(n: usize) -> Vec<Vec<f64>> {
+    let mut binomials = vec![vec![0.0; n + 1]; n + 1];
+    for i in 0 .. n + 1 {
+        binomials[i][0] = 1.0;
+        for j in 1 .. i + 1 {
+            binomials[i][j] = binomials[i - 1][j - 1] + binomials[i - 1][j];
+        }
+    }
+
+    binomials
+}
+
+// Removes a puyo of color `c` from the needed counts. A puyo which its color doesn't need
+// is used for `any`.
+fn remove_puyo(counts: &mut [usize; 5], c: usize) {
+    if counts[c] > 0 {
+        counts[c] -= 1;
+    } else if counts[4] > 0 {
+        counts[4] -= 1;
+    }
+}
+
+// Removes the puyos of a pair from the needed counts.
+fn remove_pair(counts: &[usize; 5], c1: usize, c2: usize) -> [usize; 5] {
+    let mut result = *counts;
+    remove_puyo(&mut result, c1);
+    remove_puyo(&mut result, c2);
+    result
+}
+
+// Returns the table of the expected pairs for every needed counts less than `dims`.
+// The needed counts never increase, so the table is filled in the order of the index.
+fn uniform_expected_pairs_table(dims: &[usize; 5]) -> Vec<f64> {
+    let size = dims[0] * dims[1] * dims[2] * dims[3] * dims[4];
+    let mut table = vec![0.0; size];
+    // The index 0 is the state that needs nothing.
+    for index in 1 .. size {
+        let counts = table_counts(index, dims);
+
+        // E(s) = 1 + sum_{s'} p(s') E(s'). A pair that has no needed puyo keeps s.
+        let mut sum = 0.0;
+        let mut self_probability = 0.0;
+        for c1 in 0 .. 4 {
+            for c2 in 0 .. 4 {
+                let next = remove_pair(&counts, c1, c2);
+                if next == counts {
+                    self_probability += 1.0 / 16.0;
+                } else {
+                    sum += table[table_index(&next, dims)] / 16.0;
+                }
+            }
+        }
+        table[index] = (1.0 + sum) / (1.0 - self_probability);
+    }
+
+    table
+}
+
+fn uniform_probability(counts: &[usize; 5], num_pairs: usize) -> f64 {
+    let dims = dims_of(counts);
+    let size = dims[0] * dims[1] * dims[2] * dims[3] * dims[4];
+
+    // distribution[i] is the probability that the needed counts are i after some pairs.
+    let mut distribution = vec![0.0; size];
+    distribution[table_index(counts, &dims)] = 1.0;
+
+    for _ in 0 .. num_pairs {
+        let mut next_distribution = vec![0.0; size];
+        for index in 0 .. size {
+            let p = distribution[index];
+            if p == 0.0 {
+                continue;
+            }
+            let current = table_counts(index, &dims);
+            for c1 in 0 .. 4 {
+                for c2 in 0 .. 4 {
+                    let next = remove_pair(&current, c1, c2);
+                    next_distribution[table_index(&next, &dims)] += p / 16.0;
+                }
+            }
+        }
+        distribution = next_distribution;
+    }
+
+    distribution[0]
+}
+
+// Returns the polynomial whose coefficient of x^m is the number of the ways that
+// the first m puyos of an AC sequence contain `counts`, i.e.
+// prod_i sum_{k >= counts[i]} C(64, k) x^k.
+fn ac_polynomial(binomials: &[Vec<f64>], counts: &[usize; 4]) -> Vec<f64> {
+    let mut poly = vec![0.0; AC_BAG_SIZE + 1];
+    poly[0] = 1.0;
+    for &n in counts {
+        let mut next_poly = vec![0.0; AC_BAG_SIZE + 1];
+        for (i, &a) in poly.iter().enumerate() {
+            if a == 0.0 {
+                continue;
+            }
+            for k in n .. AC_BAG_COLOR_SIZE + 1 {
+                if i + k > AC_BAG_SIZE {
+                    break;
+                }
+                next_poly[i + k] += a * binomials[AC_BAG_COLOR_SIZE][k];
+            }
+        }
+        poly = next_poly;
+    }
+
+    poly
+}
+
+// The first `num_puyos` puyos have `any` puyos besides `counts` iff they contain `counts`
+// and there are enough puyos in total.
+fn ac_probability(binomials: &[Vec<f64>], counts: &[usize; 4], any: usize, num_puyos: usize) -> f64 {
+    if counts.iter().any(|&n| n > AC_BAG_COLOR_SIZE) {
+        return 0.0;
+    }
+    if num_puyos < counts.iter().sum::<usize>() + any {
+        return 0.0;
+    }
+    if num_puyos >= AC_BAG_SIZE {
+        return 1.0;
+    }
+
+    ac_polynomial(binomials, counts)[num_puyos] / binomials[AC_BAG_SIZE][num_puyos]
+}
+
+// E[T] = sum_{n >= 0} P(T > n), where T is the number of pairs.
+fn ac_expected_pairs_of(binomials: &[Vec<f64>], counts: &[usize; 4], any: usize) -> f64 {
+    let num_needed = counts.iter().sum::<usize>() + any;
+    if counts.iter().any(|&n| n > AC_BAG_COLOR_SIZE) || num_needed > AC_BAG_SIZE {
+        return f64::INFINITY;
+    }
+    if num_needed == 0 {
+        return 0.0;
+    }
+
+    let poly = ac_polynomial(binomials, counts);
+    let mut expected = 0.0;
+    for num_pairs in 0 .. AC_BAG_SIZE / 2 {
+        if num_pairs * 2 < num_needed {
+            expected += 1.0;
+        } else {
+            expected += 1.0 - poly[num_pairs * 2] / binomials[AC_BAG_SIZE][num_pairs * 2];
+        }
+    }
+
+    expected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::PuyoColor;
+    use kumipuyo::Kumipuyo;
+    use probability::puyo_set::PuyoSet;
+
+    fn assert_near(expected: f64, actual: f64) {
+        assert!((expected - actual).abs() < 1e-9, "expected={} actual={}", expected, actual);
+    }
+
+    #[test]
+    fn test_expected_pairs_empty() {
+        let possibility = PuyoPossibility::new();
+        assert_eq!(0.0, possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::empty()));
+        assert_eq!(0.0, possibility.expected_pairs(SequenceKind::AcBag, &PuyoSet::empty()));
+    }
+
+    #[test]
+    fn test_expected_pairs_uniform() {
+        let possibility = PuyoPossibility::new();
+
+        // A pair has a red puyo with probability 7/16.
+        assert_near(16.0 / 7.0, possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(1, 0, 0, 0)));
+
+        // The order of the colors doesn't matter.
+        assert_near(possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(2, 1, 0, 3)),
+                    possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(0, 3, 2, 1)));
+
+        // More puyos need more pairs.
+        assert!(possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(2, 0, 0, 0)) <
+                possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(2, 1, 0, 0)));
+    }
+
+    #[test]
+    fn test_expected_pairs_out_of_table() {
+        let possibility = PuyoPossibility::new();
+
+        let uniform = possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(MAX_TABLE_COUNT + 1, 0, 0, 0));
+        assert!(possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(MAX_TABLE_COUNT, 0, 0, 0)) < uniform);
+
+        let ac = possibility.expected_pairs(SequenceKind::AcBag, &PuyoSet::new(MAX_TABLE_COUNT + 1, 0, 0, 0));
+        assert!(possibility.expected_pairs(SequenceKind::AcBag, &PuyoSet::new(MAX_TABLE_COUNT, 0, 0, 0)) < ac);
+
+        assert!(possibility.expected_pairs(SequenceKind::AcBag, &PuyoSet::new(65, 0, 0, 0)).is_infinite());
+    }
+
+    #[test]
+    fn test_expected_pairs_ac() {
+        let possibility = PuyoPossibility::new();
+
+        // The first pair has no red puyo with probability (192 / 256) * (191 / 255).
+        let p = possibility.probability(SequenceKind::AcBag, &PuyoSet::new(1, 0, 0, 0), 1);
+        assert_near(1.0 - (192.0 / 256.0) * (191.0 / 255.0), p);
+
+        // Drawing without replacement makes the needed puyos come a little earlier.
+        let ac = possibility.expected_pairs(SequenceKind::AcBag, &PuyoSet::new(1, 0, 0, 0));
+        assert!(ac < 16.0 / 7.0);
+        assert!(16.0 / 7.0 - ac < 0.1);
+    }
+
+    #[test]
+    fn test_expected_turns() {
+        let possibility = PuyoPossibility::new();
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+                       Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::YELLOW)];
+
+        assert_eq!(0.0, possibility.expected_turns(SequenceKind::Uniform, &PuyoSet::empty(), &seq));
+        assert_eq!(1.0, possibility.expected_turns(SequenceKind::Uniform, &PuyoSet::new(1, 1, 0, 0), &seq));
+        assert_eq!(2.0, possibility.expected_turns(SequenceKind::Uniform, &PuyoSet::new(1, 0, 2, 0), &seq));
+
+        // GREEN is not in the known pairs.
+        let expected = 2.0 + possibility.expected_pairs(SequenceKind::Uniform, &PuyoSet::new(0, 0, 0, 1));
+        assert_near(expected, possibility.expected_turns(SequenceKind::Uniform, &PuyoSet::new(1, 0, 0, 1), &seq));
+
+        // Without known pairs, it's the same as the expected pairs.
+        let ps = PuyoSet::new(1, 2, 0, 1);
+        assert_near(possibility.expected_pairs(SequenceKind::AcBag, &ps),
+                    possibility.expected_turns(SequenceKind::AcBag, &ps, &[]));
+    }
+
+    #[test]
+    fn test_expected_pairs_with_any() {
+        let possibility = PuyoPossibility::new();
+        for &kind in &[SequenceKind::Uniform, SequenceKind::AcBag] {
+            let mut ps = PuyoSet::empty();
+            ps.add_any(3);
+            assert_near(2.0, possibility.expected_pairs(kind, &ps));
+
+            // Place holders need more puyos.
+            let mut ps = PuyoSet::new(1, 0, 0, 0);
+            let without_any = possibility.expected_pairs(kind, &ps);
+            ps.add_any(2);
+            assert!(without_any < possibility.expected_pairs(kind, &ps));
+        }
+
+        // The other puyo of a pair with RED is used for the place holder.
+        let mut ps = PuyoSet::new(1, 0, 0, 0);
+        ps.add_any(1);
+        assert_near(7.0 / 16.0, possibility.probability(SequenceKind::Uniform, &ps, 1));
+        ps.add_any(1);
+        assert_eq!(0.0, possibility.probability(SequenceKind::Uniform, &ps, 1));
+        assert_eq!(0.0, possibility.probability(SequenceKind::AcBag, &ps, 1));
+
+        let mut ps = PuyoSet::empty();
+        ps.add_any(2);
+        assert_near(1.0, possibility.probability(SequenceKind::AcBag, &ps, 1));
+    }
+
+    #[test]
+    fn test_expected_turns_with_any() {
+        let possibility = PuyoPossibility::new();
+        let seq = vec![Kumipuyo::new(PuyoColor::RED, PuyoColor::BLUE),
+                       Kumipuyo::new(PuyoColor::YELLOW, PuyoColor::YELLOW)];
+
+        // BLUE is used for the place holder.
+        let mut ps = PuyoSet::new(1, 0, 0, 0);
+        ps.add_any(1);
+        assert_eq!(1.0, possibility.expected_turns(SequenceKind::Uniform, &ps, &seq));
+
+        // Place holders need puyos even if all the colors are supplied.
+        let mut ps = PuyoSet::new(1, 1, 0, 0);
+        ps.add_any(3);
+        let mut expected = PuyoSet::empty();
+        expected.add_any(1);
+        assert_near(2.0 + possibility.expected_pairs(SequenceKind::Uniform, &expected),
+                    possibility.expected_turns(SequenceKind::Uniform, &ps, &seq));
+    }
+
+    #[test]
+    fn test_probability() {
+        let possibility = PuyoPossibility::new();
+        let ps = PuyoSet::new(1, 0, 0, 0);
+
+        assert_eq!(0.0, possibility.probability(SequenceKind::Uniform, &ps, 0));
+        assert_near(7.0 / 16.0, possibility.probability(SequenceKind::Uniform, &ps, 1));
+        assert_near(1.0 - (9.0 / 16.0) * (9.0 / 16.0), possibility.probability(SequenceKind::Uniform, &ps, 2));
+
+        assert_eq!(1.0, possibility.probability(SequenceKind::Uniform, &PuyoSet::empty(), 0));
+        assert_eq!(0.0, possibility.probability(SequenceKind::Uniform, &PuyoSet::new(2, 1, 0, 0), 1));
+
+        let all = PuyoSet::new(64, 64, 64, 64);
+        assert_near(1.0, possibility.probability(SequenceKind::AcBag, &all, 128));
+        assert_eq!(0.0, possibility.probability(SequenceKind::AcBag, &all, 127));
+    }
+}
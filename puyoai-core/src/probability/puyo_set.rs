@@ -1,8 +1,17 @@
+use std::cmp;
+
+use color::{Color, PuyoColor};
+use column_puyo_list::ColumnPuyoList;
+
+/// PuyoSet counts the puyos of each normal color.
+/// `any` is the number of the puyos whose color doesn't matter, which any color satisfies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct PuyoSet {
     red: usize,
     blue: usize,
     yellow: usize,
     green: usize,
+    any: usize,
 }
 
 impl PuyoSet {
@@ -12,6 +21,7 @@ impl PuyoSet {
             blue: blue,
             yellow: yellow,
             green: green,
+            any: 0,
         }
     }
 
@@ -19,6 +29,32 @@ impl PuyoSet {
         PuyoSet::new(0, 0, 0, 0)
     }
 
+    /// Counts the puyos of `colors`. Non normal colors are ignored.
+    pub fn from_colors(colors: &[PuyoColor]) -> PuyoSet {
+        let mut ps = PuyoSet::empty();
+        for c in colors {
+            ps.add_color(*c, 1);
+        }
+        ps
+    }
+
+    /// Counts the puyos in `cpl`. A place holder is counted in `any`, since it still has to be
+    /// supplied, but any color can be there.
+    pub fn from_column_puyo_list(cpl: &ColumnPuyoList) -> PuyoSet {
+        let mut ps = PuyoSet::empty();
+        for x in 1 .. 7 {
+            for i in 0 .. cpl.size_on(x) {
+                let c = cpl.get(x, i);
+                if c.is_normal_color() {
+                    ps.add_color(c, 1);
+                } else {
+                    ps.add_any(1);
+                }
+            }
+        }
+        ps
+    }
+
     pub fn red(&self) -> usize {
         self.red
     }
@@ -34,11 +70,97 @@ impl PuyoSet {
     pub fn green(&self) -> usize {
         self.green
     }
+
+    pub fn any(&self) -> usize {
+        self.any
+    }
+
+    /// Returns the number of puyos of color `c`. 0 is returned for non normal colors.
+    pub fn count(&self, c: PuyoColor) -> usize {
+        match c {
+            PuyoColor::RED => self.red,
+            PuyoColor::BLUE => self.blue,
+            PuyoColor::YELLOW => self.yellow,
+            PuyoColor::GREEN => self.green,
+            _ => 0,
+        }
+    }
+
+    /// Returns the number of puyos including `any`.
+    pub fn size(&self) -> usize {
+        self.red + self.blue + self.yellow + self.green + self.any
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size() == 0
+    }
+
+    /// Returns the counts in the order of red, blue, yellow and green. `any` is not included.
+    pub fn to_array(&self) -> [usize; 4] {
+        [self.red, self.blue, self.yellow, self.green]
+    }
+
+    /// Adds `n` puyos of color `c`. Non normal colors are ignored.
+    pub fn add_color(&mut self, c: PuyoColor, n: usize) {
+        match c {
+            PuyoColor::RED => self.red += n,
+            PuyoColor::BLUE => self.blue += n,
+            PuyoColor::YELLOW => self.yellow += n,
+            PuyoColor::GREEN => self.green += n,
+            _ => {},
+        }
+    }
+
+    /// Adds `n` puyos whose color doesn't matter.
+    pub fn add_any(&mut self, n: usize) {
+        self.any += n;
+    }
+
+    pub fn add(&self, ps: &PuyoSet) -> PuyoSet {
+        let mut result = PuyoSet::new(self.red + ps.red,
+                                      self.blue + ps.blue,
+                                      self.yellow + ps.yellow,
+                                      self.green + ps.green);
+        result.any = self.any + ps.any;
+        result
+    }
+
+    /// Subtracts `ps` from `self`. A count never goes below 0.
+    /// The puyos in `ps` which are more than `self` needs for their color are subtracted from `any`.
+    pub fn sub(&self, ps: &PuyoSet) -> PuyoSet {
+        let mut result = PuyoSet::new(self.red.saturating_sub(ps.red),
+                                      self.blue.saturating_sub(ps.blue),
+                                      self.yellow.saturating_sub(ps.yellow),
+                                      self.green.saturating_sub(ps.green));
+        let rest = ps.red.saturating_sub(self.red) + ps.blue.saturating_sub(self.blue) +
+            ps.yellow.saturating_sub(self.yellow) + ps.green.saturating_sub(self.green) + ps.any;
+        result.any = self.any.saturating_sub(rest);
+        result
+    }
+
+    /// Returns the smallest PuyoSet that contains both `self` and `ps`.
+    pub fn union(&self, ps: &PuyoSet) -> PuyoSet {
+        let mut result = PuyoSet::new(cmp::max(self.red, ps.red),
+                                      cmp::max(self.blue, ps.blue),
+                                      cmp::max(self.yellow, ps.yellow),
+                                      cmp::max(self.green, ps.green));
+        result.any = cmp::max(self.size(), ps.size()).saturating_sub(result.size());
+        result
+    }
+
+    /// Returns true if `self` has every puyo in `ps`. The puyos in `self` which `ps` doesn't
+    /// need for their color can be used for `any` of `ps`.
+    pub fn contains(&self, ps: &PuyoSet) -> bool {
+        self.red >= ps.red && self.blue >= ps.blue && self.yellow >= ps.yellow && self.green >= ps.green &&
+            self.size() >= ps.size()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use color::PuyoColor;
+    use column_puyo_list::ColumnPuyoList;
 
     #[test]
     fn test_basic() {
@@ -53,5 +175,68 @@ mod tests {
         assert_eq!(ps.blue(), 2);
         assert_eq!(ps.yellow(), 3);
         assert_eq!(ps.green(), 4);
+        assert_eq!(ps.size(), 10);
+        assert_eq!(ps.count(PuyoColor::YELLOW), 3);
+        assert_eq!(ps.count(PuyoColor::OJAMA), 0);
+    }
+
+    #[test]
+    fn test_add_color() {
+        let mut ps = PuyoSet::empty();
+        assert!(ps.is_empty());
+
+        ps.add_color(PuyoColor::RED, 2);
+        ps.add_color(PuyoColor::GREEN, 1);
+        ps.add_color(PuyoColor::OJAMA, 3);
+        assert_eq!(PuyoSet::new(2, 0, 0, 1), ps);
+    }
+
+    #[test]
+    fn test_from() {
+        let ps = PuyoSet::from_colors(&[PuyoColor::RED, PuyoColor::RED, PuyoColor::YELLOW, PuyoColor::EMPTY]);
+        assert_eq!(PuyoSet::new(2, 0, 1, 0), ps);
+
+        let mut cpl = ColumnPuyoList::new();
+        cpl.add(1, PuyoColor::BLUE);
+        cpl.add(1, PuyoColor::IRON);
+        cpl.add(3, PuyoColor::BLUE);
+        cpl.add(6, PuyoColor::GREEN);
+        // The place holder is counted as a puyo of any color.
+        let mut expected = PuyoSet::new(0, 2, 0, 1);
+        expected.add_any(1);
+        assert_eq!(expected, PuyoSet::from_column_puyo_list(&cpl));
+    }
+
+    #[test]
+    fn test_operations() {
+        let ps1 = PuyoSet::new(1, 2, 3, 4);
+        let ps2 = PuyoSet::new(4, 3, 2, 1);
+
+        assert_eq!(PuyoSet::new(5, 5, 5, 5), ps1.add(&ps2));
+        assert_eq!(PuyoSet::new(0, 0, 1, 3), ps1.sub(&ps2));
+        assert_eq!(PuyoSet::new(4, 3, 3, 4), ps1.union(&ps2));
+
+        assert!(ps1.union(&ps2).contains(&ps1));
+        assert!(!ps1.contains(&ps2));
+    }
+
+    #[test]
+    fn test_operations_with_any() {
+        let mut ps1 = PuyoSet::new(1, 0, 0, 0);
+        ps1.add_any(2);
+
+        // RED is used for the red puyo, and the others are used for `any`.
+        let rest = ps1.sub(&PuyoSet::new(2, 1, 0, 0));
+        assert!(rest.is_empty());
+        let mut expected = PuyoSet::new(1, 0, 0, 0);
+        expected.add_any(1);
+        assert_eq!(expected, ps1.sub(&PuyoSet::new(0, 1, 0, 0)));
+
+        assert_eq!(6, ps1.add(&ps1).size());
+        assert!(PuyoSet::new(1, 1, 1, 0).contains(&ps1));
+        assert!(!PuyoSet::new(0, 1, 1, 1).contains(&ps1));
+        assert!(!PuyoSet::new(2, 0, 0, 0).contains(&ps1));
+
+        assert_eq!(PuyoSet::new(1, 2, 0, 0), ps1.union(&PuyoSet::new(0, 2, 0, 0)));
     }
 }
@@ -1,7 +1,8 @@
-use field::CoreField;
-use kumipuyo::Kumipuyo;
+use field::{CoreField, RealPlainField};
+use kumipuyo::{Kumipuyo, Kumireal};
+use kumipuyo::kumipuyo::Pair;
 use decision::Decision;
-use color::PuyoColor;
+use color::Color;
 
 const URL_PREFIX: &'static str = "http://www.puyop.com/s/";
 
@@ -17,35 +18,40 @@ const ENCODER: &'static[char] = &[
     '[', ']',
 ];
 
-fn tsumo_color_id(c: PuyoColor) -> usize {
-    match c {
-        PuyoColor::RED => 0,
-        PuyoColor::GREEN => 1,
-        PuyoColor::BLUE => 2,
-        PuyoColor::YELLOW => 3,
-        // PURPLE => 4,
+fn tsumo_color_id<C: Color>(c: C) -> usize {
+    match c.to_char() {
+        'R' => 0,
+        'G' => 1,
+        'B' => 2,
+        'Y' => 3,
+        'P' => 4,
         _ => {
             unreachable!()
         }
     }
 }
 
-fn field_color_id(c: PuyoColor) -> usize {
-    match c {
-        PuyoColor::EMPTY => 0,
-        PuyoColor::RED => 1,
-        PuyoColor::GREEN => 2,
-        PuyoColor::BLUE => 3,
-        PuyoColor::YELLOW => 4,
-        // PURPLE -> 5,
-        PuyoColor::OJAMA => 6,
+fn field_color_id<C: Color>(c: C) -> usize {
+    if c == C::empty_color() {
+        return 0;
+    }
+    if c == C::ojama_color() {
+        return 6;
+    }
+
+    match c.to_char() {
+        'R' => 1,
+        'G' => 2,
+        'B' => 3,
+        'Y' => 4,
+        'P' => 5,
         _ => {
             unreachable!()
         }
     }
 }
 
-fn encode_control(seq: &[Kumipuyo], decisions: &[Decision]) -> String {
+fn encode_control<C: Color>(seq: &[Pair<C>], decisions: &[Decision]) -> String {
     let mut ss = String::new();
     for i in 0..decisions.len() {
         let kp = &seq[i];
@@ -58,24 +64,22 @@ fn encode_control(seq: &[Kumipuyo], decisions: &[Decision]) -> String {
     ss
 }
 
-fn encode_field(field: &CoreField) -> String {
-    if field.is_all_cleared() {
-        return "".to_owned();
-    }
-
+// Rows above the highest puyo are omitted, so an empty field is an empty string.
+fn encode_field<C: Color, F: Fn(usize, usize) -> C>(color_at: F) -> String {
     let mut ss = String::new();
     let mut start = false;
 
     for y in (1..14).rev() {
         for px in &[1, 3, 5] {
             let x = *px;
-            if !start && field.is_empty(x, y) && field.is_empty(x + 1, y) {
+            let (c1, c2) = (color_at(x, y), color_at(x + 1, y));
+            if !start && c1 == C::empty_color() && c2 == C::empty_color() {
                 continue;
             }
 
             let mut d = 0usize;
-            d += field_color_id(field.color(x, y)) * 8;
-            d += field_color_id(field.color(x + 1, y));
+            d += field_color_id(c1) * 8;
+            d += field_color_id(c2);
             assert!(d < 64);
             start = true;
             ss.push(ENCODER[d] as char);
@@ -85,18 +89,30 @@ fn encode_field(field: &CoreField) -> String {
     ss
 }
 
-pub fn make_puyop_url(field: &CoreField, seq: &[Kumipuyo], decisions: &[Decision]) -> String {
-    if seq.is_empty() && decisions.is_empty() {
-        format!("{}{}", URL_PREFIX, encode_field(field))
+fn make_url(encoded_field: String, encoded_control: String) -> String {
+    if encoded_control.is_empty() {
+        format!("{}{}", URL_PREFIX, encoded_field)
     } else {
-        format!("{}{}_{}", URL_PREFIX, encode_field(field), encode_control(seq, decisions))
+        format!("{}{}_{}", URL_PREFIX, encoded_field, encoded_control)
     }
 }
 
+pub fn make_puyop_url(field: &CoreField, seq: &[Kumipuyo], decisions: &[Decision]) -> String {
+    make_url(encode_field(|x, y| field.color(x, y)), encode_control(seq, decisions))
+}
+
+/// Same as `make_puyop_url`, but for RealColor, which has PURPLE for 5 color games.
+pub fn make_puyop_url_from_real_field(field: &RealPlainField, seq: &[Kumireal], decisions: &[Decision]) -> String {
+    make_url(encode_field(|x, y| field.color(x, y)), encode_control(seq, decisions))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use field::CoreField;
+    use color::RealColor;
+    use decision::Decision;
+    use field::{CoreField, RealPlainField};
+    use kumipuyo::Kumireal;
 
     #[test]
     fn test_make_puyop_url() {
@@ -110,4 +126,21 @@ mod test {
         assert_eq!("http://www.puyop.com/s/420Aa9r9hj",
                    make_puyop_url(&cf, &[], &[]));
     }
+
+    #[test]
+    fn test_make_puyop_url_from_real_field() {
+        let field = RealPlainField::from_str(concat!(
+            ".....Y",
+            ".G..YY",
+            "RGRRBB",
+            "RRGRGB",
+        ));
+        assert_eq!("http://www.puyop.com/s/420Aa9r9hj",
+                   make_puyop_url_from_real_field(&field, &[], &[]));
+
+        let field = RealPlainField::from_str("P.....");
+        let seq = vec![Kumireal::new(RealColor::PURPLE, RealColor::PURPLE)];
+        assert_eq!("http://www.puyop.com/s/E00_oo",
+                   make_puyop_url_from_real_field(&field, &seq, &[Decision::new(3, 0)]));
+    }
 }
@@ -2,6 +2,7 @@ use field::{self, CoreField};
 use column_puyo_list::ColumnPuyoList;
 use color::{PuyoColor, NUM_PUYO_COLORS};
 use rensa_detector::PurposeForFindingRensa;
+use rensa_result::RensaResult;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mode {
@@ -53,7 +54,8 @@ impl RensaDetector {
                                max_complement_puyo, self.max_height, callback)
             },
             Mode::Float => {
-                unimplemented!()
+                detect_by_float(original_field, prohibits, purpose,
+                                max_complement_puyo, self.max_height, callback)
             }
         }
     }
@@ -138,9 +140,146 @@ pub fn detect_by_drop<Callback>(original_field: &CoreField,
     });
 }
 
+/// Detects rensa by `float` strategy.
+///
+/// Unlike `drop`, complement puyos are put beside an existing puyo at the same height
+/// even if there is no puyo below them. The empty cells below them are filled with IRON,
+/// a place holder, both in the field passed to `callback` and in `ColumnPuyoList`,
+/// so dropping the `ColumnPuyoList` on `original_field` makes the same field.
+pub fn detect_by_float<Callback>(original_field: &CoreField,
+                                 prohibits: &[bool],
+                                 purpose: PurposeForFindingRensa,
+                                 max_complement_puyos: usize,
+                                 max_puyo_height: usize,
+                                 mut callback: Callback)
+                                 where Callback: FnMut(CoreField, &ColumnPuyoList) {
+    let mut visited = [[[false; NUM_PUYO_COLORS]; field::MAP_HEIGHT]; field::MAP_WIDTH];
+
+    let normal_color_bits = original_field.field().normal_color_bits();
+    let empty_bits = original_field.field().bits(PuyoColor::EMPTY);
+    let edge_bits = (normal_color_bits & empty_bits.expand_edge()).masked_field_12();
+
+    edge_bits.iterate_bit_position(|x, y| {
+        debug_assert!(original_field.is_normal_color(x, y));
+        let c = original_field.color(x, y);
+
+        for d in &[-1isize, 0isize, 1isize] {
+            let xd = (x as isize + *d) as usize;
+            if xd == 0 || field::WIDTH < xd || prohibits[xd] {
+                continue;
+            }
+
+            // The first complement puyo is put on (xd, yd).
+            let yd = if *d == 0 { y + 1 } else { y };
+            if !original_field.is_empty(xd, yd) || original_field.height(xd) >= yd {
+                continue;
+            }
+            if *d == 0 && purpose == PurposeForFindingRensa::ForFire && !original_field.is_connected(x, y) {
+                // See the comment in detect_by_drop.
+                continue;
+            }
+            if visited[xd][yd][c as usize] {
+                continue;
+            }
+            visited[xd][yd][c as usize] = true;
+
+            let mut cf: CoreField = (*original_field).clone();
+            let mut necessary_puyos = 0;
+            let mut ok = false;
+            while necessary_puyos < max_complement_puyos {
+                let y_put = yd + necessary_puyos;
+                if y_put > max_puyo_height || y_put > 13 {
+                    break;
+                }
+
+                cf.set_puyo_on_place_holders(xd, y_put, c);
+                necessary_puyos += 1;
+                if cf.count_connected_max4_with_color(xd, y_put, c) >= 4 {
+                    ok = true;
+                    break;
+                }
+            }
+
+            if !ok {
+                continue;
+            }
+
+            let num_place_holders = yd - 1 - original_field.height(xd);
+            let mut cpl = ColumnPuyoList::new();
+            if !cpl.add_multi(xd, PuyoColor::IRON, num_place_holders) || !cpl.add_multi(xd, c, necessary_puyos) {
+                continue;
+            }
+
+            callback(cf, &cpl);
+        }
+    });
+}
+
+/// Detects rensas that need several rounds of complement puyos.
+///
+/// In each round, complement puyos are found by `drop` strategy on the field where
+/// the rensa found in the previous round has vanished once. All the complement puyos
+/// are put on `original_field` at once, and `callback` receives the field before the
+/// rensa is fired, the combined `ColumnPuyoList`, and the `RensaResult`.
+///
+/// Since a complement found after a vanish might land on another position on `original_field`,
+/// the combined complement is simulated on `original_field` again, and is used only if
+/// the rensa is longer than the one in the previous round.
+pub fn detect_iteratively<Callback>(original_field: &CoreField,
+                                    max_iteration: usize,
+                                    max_complement_puyos: usize,
+                                    max_total_puyos: usize,
+                                    max_puyo_height: usize,
+                                    mut callback: Callback)
+                                    where Callback: FnMut(&CoreField, &ColumnPuyoList, &RensaResult) {
+    debug_assert!(max_iteration >= 1);
+    detect_iteratively_internal(original_field, original_field, max_iteration, &ColumnPuyoList::new(), 0,
+                                max_complement_puyos, max_total_puyos, max_puyo_height, &mut callback);
+}
+
+fn detect_iteratively_internal<Callback>(original_field: &CoreField,
+                                         current_field: &CoreField,
+                                         rest_iteration: usize,
+                                         accumulated_cpl: &ColumnPuyoList,
+                                         previous_chain: usize,
+                                         max_complement_puyos: usize,
+                                         max_total_puyos: usize,
+                                         max_puyo_height: usize,
+                                         callback: &mut Callback)
+                                         where Callback: FnMut(&CoreField, &ColumnPuyoList, &RensaResult) {
+    let no_prohibits = [false; field::MAP_WIDTH];
+    detect_by_drop(current_field, &no_prohibits, PurposeForFindingRensa::ForKey,
+                   max_complement_puyos, max_puyo_height, |complemented_field, cpl| {
+        let mut combined_cpl = accumulated_cpl.clone();
+        if !combined_cpl.merge(cpl) || max_total_puyos < combined_cpl.size() {
+            return;
+        }
+
+        let mut cf = original_field.clone();
+        if !cf.drop_column_puyo_list_with_max_height(&combined_cpl, max_puyo_height) {
+            return;
+        }
+
+        let rensa_result = cf.clone().simulate();
+        if rensa_result.chain <= previous_chain {
+            return;
+        }
+        callback(&cf, &combined_cpl, &rensa_result);
+
+        if rest_iteration > 1 {
+            let mut next_field = complemented_field;
+            next_field.vanish_drop(1);
+            detect_iteratively_internal(original_field, &next_field, rest_iteration - 1, &combined_cpl,
+                                        rensa_result.chain, max_complement_puyos, max_total_puyos,
+                                        max_puyo_height, callback);
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use color::PuyoColor;
     use column_puyo_list::ColumnPuyoList;
     use field::CoreField;
     use rensa_detector::PurposeForFindingRensa;
@@ -181,4 +320,119 @@ mod tests {
         }
         assert!(found);
     }
+
+    #[test]
+    fn test_detect_by_float() {
+        let original = CoreField::from_str(concat!(
+            "R.....",
+            "RR....",
+            "GB....",
+            "GB...."));
+
+        // (3, 3) is put beside RR, and (3, 2) and (3, 1) are filled with place holders.
+        let expected = CoreField::from_str(concat!(
+            "R.....",
+            "RRR...",
+            "GB&...",
+            "GB&..."));
+
+        let mut found = false;
+        {
+            let callback = |actual: CoreField, cpl: &ColumnPuyoList| {
+                if actual != expected {
+                    return;
+                }
+
+                assert!(!found);
+                found = true;
+                assert_eq!(3, cpl.size_on(3));
+                assert_eq!(PuyoColor::IRON, cpl.get(3, 0));
+                assert_eq!(PuyoColor::IRON, cpl.get(3, 1));
+                assert_eq!(PuyoColor::RED, cpl.get(3, 2));
+                assert_eq!(3, actual.height(3));
+            };
+
+            let no_prohibits = &[false; 8];
+            detect_by_float(&original, no_prohibits, PurposeForFindingRensa::ForFire, 1, 13, callback);
+        }
+        assert!(found);
+
+        // The drop strategy cannot find it with 1 puyo.
+        let mut found_by_drop = false;
+        {
+            let callback = |actual: CoreField, _: &ColumnPuyoList| {
+                if actual.color(3, 3) == PuyoColor::RED {
+                    found_by_drop = true;
+                }
+            };
+
+            let no_prohibits = &[false; 8];
+            detect_by_drop(&original, no_prohibits, PurposeForFindingRensa::ForFire, 1, 12, callback);
+        }
+        assert!(!found_by_drop);
+    }
+
+    #[test]
+    fn test_detect_iteratively() {
+        // Putting R on column 2 vanishes the R puyos, and then B on column 1 falls.
+        // Putting BB on column 3 in advance makes 2 rensa, which needs 2 rounds.
+        let original = CoreField::from_str(concat!(
+            "B.....",
+            "R.....",
+            "R.....",
+            "RB...."));
+
+        let mut found = false;
+        detect_iteratively(&original, 2, 2, 4, 12, |cf, cpl, rensa_result| {
+            let mut expected = original.clone();
+            assert!(expected.drop_column_puyo_list(cpl));
+            assert_eq!(expected, *cf);
+            assert!(rensa_result.chain >= 1);
+
+            if cpl.size() == 3 && cpl.size_on(2) == 1 && cpl.size_on(3) == 2 {
+                assert_eq!(PuyoColor::RED, cpl.get(2, 0));
+                assert_eq!(PuyoColor::BLUE, cpl.get(3, 0));
+                assert_eq!(PuyoColor::BLUE, cpl.get(3, 1));
+                assert_eq!(2, rensa_result.chain);
+                found = true;
+            }
+        });
+        assert!(found);
+    }
+
+    #[test]
+    fn test_detect_float_strategy_limits() {
+        let original = CoreField::from_str(concat!(
+            "R.....",
+            "RR....",
+            "GB....",
+            "GB...."));
+
+        // Nothing is put on a prohibited column.
+        let detector = RensaDetector::default_float_strategy();
+        let mut prohibits = [false; 8];
+        prohibits[3] = true;
+        let mut found = false;
+        detector.detect(&original, PurposeForFindingRensa::ForFire, &prohibits, |_, cpl| {
+            assert_eq!(0, cpl.size_on(3));
+            found = true;
+        });
+        assert!(found);
+
+        // Nothing is put above the max height 3, but RED on (3, 3) is still found.
+        let detector = RensaDetector::new(Mode::Float, 3, 3, 3);
+        let no_prohibits = &[false; 8];
+        let mut found = false;
+        detector.detect(&original, PurposeForFindingRensa::ForFire, no_prohibits, |cf, cpl| {
+            for x in 1..7 {
+                if cpl.size_on(x) > 0 {
+                    assert!(cf.height(x) <= 3);
+                }
+            }
+            if cf.color(3, 3) == PuyoColor::RED {
+                found = true;
+            }
+        });
+        assert!(found);
+    }
 }
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct RensaResult {
     pub chain: usize,
     pub score: usize,
@@ -0,0 +1,148 @@
+use field;
+use field_bit::FieldBit;
+use rensa_tracker::RensaTracker;
+
+/// RensaChainTracker records the chain number when each puyo vanished.
+/// The positions are the ones in the original field, i.e. before the rensa starts.
+pub struct RensaChainTracker {
+    // erased_at[x][y] is the chain number when the puyo originally at (x, y) vanished.
+    // 0 means the puyo didn't vanish.
+    erased_at: [[usize; field::MAP_HEIGHT]; field::MAP_WIDTH],
+    // original_y[x][y] is the original y of the puyo which is at (x, y) now.
+    original_y: [[usize; field::MAP_HEIGHT]; field::MAP_WIDTH],
+}
+
+impl RensaChainTracker {
+    pub fn new() -> RensaChainTracker {
+        let mut original_y = [[0; field::MAP_HEIGHT]; field::MAP_WIDTH];
+        for x in 0 .. field::MAP_WIDTH {
+            for y in 0 .. field::MAP_HEIGHT {
+                original_y[x][y] = y;
+            }
+        }
+
+        RensaChainTracker {
+            erased_at: [[0; field::MAP_HEIGHT]; field::MAP_WIDTH],
+            original_y: original_y,
+        }
+    }
+
+    /// Returns the chain number when the puyo originally at (x, y) vanished.
+    /// 0 is returned if it didn't vanish.
+    pub fn erased_at(&self, x: usize, y: usize) -> usize {
+        self.erased_at[x][y]
+    }
+
+    pub fn is_erased(&self, x: usize, y: usize) -> bool {
+        self.erased_at[x][y] != 0
+    }
+
+    // Moves the bits in `old_bits` to `new_bits` in order. The first column of `bits` is `x_offset`.
+    // This is the same as pdep(pext(_, old_bits), new_bits) in BitField::drop_after_vanish_fast.
+    fn track_drop_half(&mut self, x_offset: usize, old_bits: u64, new_bits: u64) {
+        let mut moved = [[0; field::MAP_HEIGHT]; 4];
+        let mut old_bits = old_bits;
+        let mut new_bits = new_bits;
+        while old_bits != 0 && new_bits != 0 {
+            let old_bit = old_bits.trailing_zeros() as usize;
+            let new_bit = new_bits.trailing_zeros() as usize;
+            moved[new_bit >> 4][new_bit & 0xF] = self.original_y[x_offset + (old_bit >> 4)][old_bit & 0xF];
+            old_bits = old_bits & (old_bits - 1);
+            new_bits = new_bits & (new_bits - 1);
+        }
+
+        for i in 0 .. 4 {
+            self.original_y[x_offset + i] = moved[i];
+        }
+    }
+}
+
+impl RensaTracker for RensaChainTracker {
+    fn track_coef(&mut self, _nth_chain: usize, _num_erased: usize, _long_bonus_coef: usize, _color_bonus_coef: usize) {}
+
+    fn track_vanish(&mut self, nth_chain: usize, vanished: &FieldBit, _ojama_vanished: &FieldBit) {
+        // `vanished` contains `ojama_vanished`.
+        let original_y = &self.original_y;
+        let erased_at = &mut self.erased_at;
+        vanished.iterate_bit_position(|x, y| {
+            erased_at[x][original_y[x][y]] = nth_chain;
+        });
+    }
+
+    fn track_holes(&mut self, _holes: &FieldBit) {}
+
+    fn track_drop(&mut self, old_low_bits: u64, old_high_bits: u64, new_low_bits: u64, new_high_bits: u64) {
+        if new_low_bits != 0xFFFFFFFFFFFFFFFF {
+            self.track_drop_half(0, old_low_bits, new_low_bits);
+        }
+        if new_high_bits != 0xFFFFFFFFFFFFFFFF {
+            self.track_drop_half(4, old_high_bits, new_high_bits);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RensaChainTracker;
+    use field_bit::FieldBit;
+    use rensa_tracker::RensaTracker;
+
+    #[test]
+    fn test_track_vanish_and_drop() {
+        let mut tracker = RensaChainTracker::new();
+
+        // (1, 1) and (1, 2) vanish in the 1st chain.
+        let mut vanished = FieldBit::empty();
+        vanished.set(1, 1);
+        vanished.set(1, 2);
+        tracker.track_vanish(1, &vanished, &FieldBit::empty());
+
+        // Column 1 drops by 2. The remaining bits are y = 0, 3, 4, ..., 15.
+        let old_low_bits = !(((1u64 << 1) | (1u64 << 2)) << 16);
+        let new_low_bits = !(((1u64 << 14) | (1u64 << 15)) << 16);
+        tracker.track_drop(old_low_bits, 0xFFFFFFFFFFFFFFFF, new_low_bits, 0xFFFFFFFFFFFFFFFF);
+
+        // The puyo originally at (1, 3) is now at (1, 1).
+        let mut vanished = FieldBit::empty();
+        vanished.set(1, 1);
+        tracker.track_vanish(2, &vanished, &FieldBit::empty());
+
+        assert_eq!(1, tracker.erased_at(1, 1));
+        assert_eq!(1, tracker.erased_at(1, 2));
+        assert_eq!(2, tracker.erased_at(1, 3));
+        assert_eq!(0, tracker.erased_at(1, 4));
+        assert!(!tracker.is_erased(2, 1));
+    }
+}
+
+#[cfg(all(test, target_feature = "avx2", target_feature="bmi2"))]
+mod tests_for_avx2 {
+    use super::RensaChainTracker;
+    use field::BitField;
+
+    #[test]
+    fn test_simulate() {
+        let mut bf = BitField::from_str(concat!(
+            "R...RR",
+            "RGBRYR",
+            "RRGBBY",
+            "GGBYYR"));
+        let mut tracker = RensaChainTracker::new();
+        let rensa_result = bf.simulate_with_tracker(&mut tracker);
+
+        assert_eq!(5, rensa_result.chain);
+
+        let expected = [
+            [1, 0, 0, 0, 5, 5],
+            [1, 2, 3, 5, 4, 5],
+            [1, 1, 2, 3, 3, 4],
+            [2, 2, 3, 4, 4, 5],
+        ];
+        for (i, row) in expected.iter().enumerate() {
+            let y = 4 - i;
+            for x in 1 .. 7 {
+                assert_eq!(row[x - 1], tracker.erased_at(x, y), "x={} y={}", x, y);
+            }
+        }
+    }
+}
@@ -31,6 +31,7 @@ impl RensaTracker for RensaCoefTracker {
         self.color_bonus_coef[nth_chain] = color_bonus_coef;
     }
     fn track_vanish(&mut self, _nth_chain: usize, _vanished: &FieldBit, _ojama_vanished: &FieldBit) {}
+    fn track_holes(&mut self, _holes: &FieldBit) {}
     fn track_drop(&mut self, _old_low_bits: u64, _old_high_bits: u64, _new_low_bits: u64, _new_high_bits: u64) {}
 }
 
@@ -0,0 +1,173 @@
+use field;
+use field_bit::FieldBit;
+use rensa_tracker::RensaTracker;
+
+/// RensaStep is what happened in one chain.
+#[derive(Clone, Copy, Debug)]
+pub struct RensaStep {
+    /// The vanished puyos. This includes the vanished ojama.
+    pub vanished: FieldBit,
+    pub ojama_vanished: FieldBit,
+    /// `drops[x]` is the number of rows the puyos on column `x` drop after the vanishment, i.e.
+    /// the number of the vanished puyos below the topmost remaining puyo of the column.
+    /// The vanished puyos with nothing above don't count.
+    pub drops: [usize; field::MAP_WIDTH],
+}
+
+impl RensaStep {
+    fn new(vanished: FieldBit, ojama_vanished: FieldBit) -> RensaStep {
+        RensaStep {
+            vanished: vanished,
+            ojama_vanished: ojama_vanished,
+            drops: [0; field::MAP_WIDTH],
+        }
+    }
+
+    pub fn max_drops(&self) -> usize {
+        *self.drops.iter().max().unwrap()
+    }
+}
+
+/// RensaVanishingPositionTracker records the vanished positions and the drops for each chain.
+/// The positions are the ones in the field at the time of the vanishment.
+pub struct RensaVanishingPositionTracker {
+    steps: Vec<RensaStep>,
+}
+
+impl RensaVanishingPositionTracker {
+    pub fn new() -> RensaVanishingPositionTracker {
+        RensaVanishingPositionTracker {
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn num_chains(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Returns the step of the `nth_chain` (1-origin).
+    pub fn step(&self, nth_chain: usize) -> &RensaStep {
+        debug_assert!(1 <= nth_chain && nth_chain <= self.steps.len(), "nth_chain={}", nth_chain);
+        &self.steps[nth_chain - 1]
+    }
+
+    pub fn steps(&self) -> &[RensaStep] {
+        &self.steps
+    }
+}
+
+impl RensaTracker for RensaVanishingPositionTracker {
+    fn track_coef(&mut self, _nth_chain: usize, _num_erased: usize, _long_bonus_coef: usize, _color_bonus_coef: usize) {}
+
+    fn track_vanish(&mut self, nth_chain: usize, vanished: &FieldBit, ojama_vanished: &FieldBit) {
+        debug_assert_eq!(nth_chain, self.steps.len() + 1);
+        self.steps.push(RensaStep::new(*vanished, *ojama_vanished));
+    }
+
+    fn track_holes(&mut self, holes: &FieldBit) {
+        if let Some(step) = self.steps.last_mut() {
+            let drops = &mut step.drops;
+            holes.iterate_bit_position(|x, _| {
+                drops[x] += 1;
+            });
+        }
+    }
+
+    fn track_drop(&mut self, _old_low_bits: u64, _old_high_bits: u64, _new_low_bits: u64, _new_high_bits: u64) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RensaVanishingPositionTracker;
+    use field_bit::FieldBit;
+    use rensa_tracker::RensaTracker;
+
+    #[test]
+    fn test_track() {
+        let mut tracker = RensaVanishingPositionTracker::new();
+
+        let mut vanished = FieldBit::empty();
+        vanished.set(1, 1);
+        tracker.track_vanish(1, &vanished, &FieldBit::empty());
+        // A puyo remains above (1, 1).
+        tracker.track_holes(&vanished);
+        tracker.track_drop(!(1 << 17), !0, 0xFFFF_FFFF_7FFF_FFFF, !0);
+
+        let mut ojama_vanished = FieldBit::empty();
+        ojama_vanished.set(2, 1);
+        let mut vanished = ojama_vanished;
+        vanished.set(3, 1);
+        tracker.track_vanish(2, &vanished, &ojama_vanished);
+        // Nothing remains above (3, 1).
+        tracker.track_holes(&ojama_vanished);
+        tracker.track_drop(!((1 << 33) | (1 << 49)), !0, 0x7FFF_7FFF_FFFF_FFFF, !0);
+
+        let mut vanished = FieldBit::empty();
+        for x in 1..5 {
+            vanished.set(x, 1);
+        }
+        tracker.track_vanish(3, &vanished, &FieldBit::empty());
+        // Nothing remains at all.
+        tracker.track_holes(&FieldBit::empty());
+        tracker.track_drop(!0x0002_0002_0002_0000, !0x2, 0x7FFF_7FFF_7FFF_FFFF, 0xFFFF_FFFF_FFFF_7FFF);
+
+        assert_eq!(3, tracker.num_chains());
+        assert!(tracker.step(1).vanished.get(1, 1));
+        assert_eq!(1, tracker.step(1).max_drops());
+        assert_eq!(1, tracker.step(1).drops[1]);
+        assert!(tracker.step(2).vanished.get(2, 1));
+        assert!(tracker.step(2).ojama_vanished.get(2, 1));
+        assert!(!tracker.step(2).ojama_vanished.get(3, 1));
+        assert_eq!([0, 0, 1, 0, 0, 0, 0, 0], tracker.step(2).drops);
+        assert_eq!(4, tracker.step(3).vanished.popcount());
+        assert_eq!(0, tracker.step(3).max_drops());
+    }
+}
+
+#[cfg(all(test, target_feature = "avx2", target_feature="bmi2"))]
+mod tests_for_avx2 {
+    use super::RensaVanishingPositionTracker;
+    use field::BitField;
+
+    #[test]
+    fn test_simulate() {
+        let mut bf = BitField::from_str(concat!(
+            "Y.....",
+            "B.....",
+            "BO....",
+            "RBB...",
+            "RRRB.."));
+        let mut tracker = RensaVanishingPositionTracker::new();
+        let rensa_result = bf.simulate_with_tracker(&mut tracker);
+
+        assert_eq!(2, rensa_result.chain);
+        assert_eq!(2, tracker.num_chains());
+
+        let step = tracker.step(1);
+        assert!(step.vanished.get(1, 1) && step.vanished.get(2, 1) && step.vanished.get(3, 1) && step.vanished.get(1, 2));
+        assert!(step.ojama_vanished.is_empty());
+        assert_eq!(2, step.drops[1]);
+        assert_eq!(1, step.drops[2]);
+        assert_eq!(1, step.drops[3]);
+        assert_eq!(0, step.drops[4]);
+
+        let step = tracker.step(2);
+        // 5 B and 1 ojama.
+        assert_eq!(6, step.vanished.popcount());
+        assert_eq!(1, step.ojama_vanished.popcount());
+        assert!(step.ojama_vanished.get(2, 2));
+        // Only Y remains above the vanished puyos.
+        assert_eq!([0, 2, 0, 0, 0, 0, 0, 0], step.drops);
+    }
+
+    #[test]
+    fn test_simulate_nothing_above() {
+        let mut bf = BitField::from_str("RRRR..");
+        let mut tracker = RensaVanishingPositionTracker::new();
+        let rensa_result = bf.simulate_with_tracker(&mut tracker);
+
+        assert_eq!(1, rensa_result.chain);
+        assert_eq!(0, tracker.step(1).max_drops());
+        assert_eq!([0; 8], tracker.step(1).drops);
+    }
+}
@@ -1,22 +1,337 @@
-use puyoai_core::field::PuyoPlainField;
-use puyoai_core::kumipuyo::Kumipuyo;
+use puyoai_core::color::{Color, RealColor};
+use puyoai_core::field::RealPlainField;
+use puyoai_core::kumipuyo::Kumireal;
 use puyoai_core::kumipuyo::KumipuyoPos;
 
 use user_event::UserEvent;
 use game_result::GameResult;
 
+// The number of rows sent in YF and OF. The 14th row is invisible to players.
+const NUM_FIELD_ROWS: usize = 13;
+
+#[derive(Clone)]
 pub struct PlayerFrameRequest {
-    pub field: PuyoPlainField,
-    pub seq: Vec<Kumipuyo>,
+    pub field: RealPlainField,
+    pub seq: Vec<Kumireal>,
     pub pos: KumipuyoPos,
     pub event: UserEvent,
     pub score: u32,
     pub ojama: u32,
 }
 
+impl PlayerFrameRequest {
+    pub fn new() -> PlayerFrameRequest {
+        PlayerFrameRequest {
+            field: RealPlainField::new(),
+            seq: Vec::new(),
+            pos: KumipuyoPos::new(0, 0, 0),
+            event: UserEvent::new(),
+            score: 0,
+            ojama: 0,
+        }
+    }
+}
+
+/// FrameRequest is sent from the server to each client every frame.
+///
+/// The text format is compatible with the C++ puyoai duel server.
+///
+/// ```text
+/// ID=1 STATE=8 YF=...(78 chars) OF=... YP=RBYG OP=RBYG YX=3 YY=12 YR=0 OX=3 OY=12 OR=0 YS=0 OS=0 YO=0 OO=0
+/// ```
+///
+/// "Y" is the player who receives the request, and "O" is the opponent.
+/// END (and MATCHEND) appears only when the game has finished.
+///
+/// The colors are RealColor, so PURPLE ('P') appears in 5 color games.
+/// A client maps them to PuyoColor with ColorMap.
 pub struct FrameRequest {
     pub frame_id: i32,
     pub game_result: GameResult,
     pub match_end: bool,
     pub player_frame_request: [PlayerFrameRequest; 2],
 }
+
+fn field_to_string(field: &RealPlainField) -> String {
+    let mut result = String::new();
+    for y in (1..(NUM_FIELD_ROWS + 1)).rev() {
+        for x in 1..7 {
+            let c = field.color(x, y);
+            result.push(if c == RealColor::EMPTY { '.' } else { c.to_char() });
+        }
+    }
+
+    result
+}
+
+fn seq_to_string(seq: &[Kumireal]) -> String {
+    let mut result = String::new();
+    for kp in seq {
+        result.push(kp.axis().to_char());
+        result.push(kp.child().to_char());
+    }
+
+    result
+}
+
+fn parse_field(s: &str) -> Result<RealPlainField, String> {
+    if s.len() % 6 != 0 || s.len() > NUM_FIELD_ROWS * 6 {
+        return Err(format!("invalid field length: {}", s.len()));
+    }
+    for c in s.chars() {
+        match c {
+            '.' | 'O' | 'R' | 'B' | 'Y' | 'G' | 'P' => {},
+            _ => return Err(format!("invalid field character: {}", c)),
+        }
+    }
+
+    Ok(RealPlainField::from_str(s))
+}
+
+fn parse_puyo_color(c: char) -> Result<RealColor, String> {
+    match c {
+        'R' | 'B' | 'Y' | 'G' | 'P' => Ok(RealColor::from_byte(c as u8)),
+        _ => Err(format!("invalid kumipuyo color: {}", c)),
+    }
+}
+
+fn parse_seq(s: &str) -> Result<Vec<Kumireal>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() % 2 != 0 {
+        return Err(format!("invalid kumipuyo sequence length: {}", chars.len()));
+    }
+
+    let mut seq = Vec::new();
+    for pair in chars.chunks(2) {
+        let axis = try!(parse_puyo_color(pair[0]));
+        let child = try!(parse_puyo_color(pair[1]));
+        seq.push(Kumireal::new(axis, child));
+    }
+
+    Ok(seq)
+}
+
+fn parse_int<T: ::std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
+impl FrameRequest {
+    /// Returns the line sent to a client. player_frame_request[0] is written as
+    /// "Y" (you) and player_frame_request[1] is written as "O" (opponent).
+    pub fn to_string(&self) -> String {
+        let me = &self.player_frame_request[0];
+        let op = &self.player_frame_request[1];
+
+        let mut result = String::new();
+
+        result.push_str(&format!("ID={}", self.frame_id));
+        if self.game_result.is_finished() {
+            result.push_str(&format!(" END={}", self.game_result.to_int()));
+        }
+        if self.match_end {
+            result.push_str(" MATCHEND=1");
+        }
+
+        // STATE interleaves the events: you at bit 2i, the opponent at bit 2i+1.
+        let mut state = 0;
+        for i in 0..16 {
+            state |= ((me.event.to_bits() >> i) & 1) << (2 * i);
+            state |= ((op.event.to_bits() >> i) & 1) << (2 * i + 1);
+        }
+        result.push_str(&format!(" STATE={}", state));
+
+        result.push_str(&format!(" YF={}", field_to_string(&me.field)));
+        result.push_str(&format!(" OF={}", field_to_string(&op.field)));
+        result.push_str(&format!(" YP={}", seq_to_string(&me.seq)));
+        result.push_str(&format!(" OP={}", seq_to_string(&op.seq)));
+        result.push_str(&format!(" YX={} YY={} YR={}", me.pos.axis_x(), me.pos.axis_y(), me.pos.rot()));
+        result.push_str(&format!(" OX={} OY={} OR={}", op.pos.axis_x(), op.pos.axis_y(), op.pos.rot()));
+        result.push_str(&format!(" YS={} OS={}", me.score, op.score));
+        result.push_str(&format!(" YO={} OO={}", me.ojama, op.ojama));
+
+        result
+    }
+
+    /// Parses a line written by `to_string`. Unknown keys are ignored.
+    pub fn parse(s: &str) -> Result<FrameRequest, String> {
+        let mut frame_id = None;
+        let mut game_result = GameResult::Playing;
+        let mut match_end = false;
+        let mut me = PlayerFrameRequest::new();
+        let mut op = PlayerFrameRequest::new();
+        let mut pos = [[0i32; 3]; 2];
+
+        for term in s.split_whitespace() {
+            let eq = match term.find('=') {
+                Some(eq) => eq,
+                None => return Err(format!("'=' is missing: {}", term)),
+            };
+            let (key, value) = (&term[..eq], &term[eq + 1..]);
+
+            match key {
+                "ID" => frame_id = Some(try!(parse_int::<i32>(key, value))),
+                "END" => {
+                    let v = try!(parse_int::<i32>(key, value));
+                    game_result = try!(GameResult::from_int(v).ok_or(format!("invalid game result: {}", v)));
+                },
+                "MATCHEND" => match_end = value == "1",
+                "STATE" => {
+                    let state = try!(parse_int::<u32>(key, value));
+                    let (mut me_bits, mut op_bits) = (0, 0);
+                    for i in 0..16 {
+                        me_bits |= ((state >> (2 * i)) & 1) << i;
+                        op_bits |= ((state >> (2 * i + 1)) & 1) << i;
+                    }
+                    me.event = UserEvent::from_bits(me_bits);
+                    op.event = UserEvent::from_bits(op_bits);
+                },
+                "YF" => me.field = try!(parse_field(value)),
+                "OF" => op.field = try!(parse_field(value)),
+                "YP" => me.seq = try!(parse_seq(value)),
+                "OP" => op.seq = try!(parse_seq(value)),
+                "YX" => pos[0][0] = try!(parse_int::<i32>(key, value)),
+                "YY" => pos[0][1] = try!(parse_int::<i32>(key, value)),
+                "YR" => pos[0][2] = try!(parse_int::<i32>(key, value)),
+                "OX" => pos[1][0] = try!(parse_int::<i32>(key, value)),
+                "OY" => pos[1][1] = try!(parse_int::<i32>(key, value)),
+                "OR" => pos[1][2] = try!(parse_int::<i32>(key, value)),
+                "YS" => me.score = try!(parse_int::<u32>(key, value)),
+                "OS" => op.score = try!(parse_int::<u32>(key, value)),
+                "YO" => me.ojama = try!(parse_int::<u32>(key, value)),
+                "OO" => op.ojama = try!(parse_int::<u32>(key, value)),
+                _ => {},
+            }
+        }
+
+        let frame_id = try!(frame_id.ok_or("ID is missing".to_string()));
+        me.pos = KumipuyoPos::new(pos[0][0], pos[0][1], pos[0][2]);
+        op.pos = KumipuyoPos::new(pos[1][0], pos[1][1], pos[1][2]);
+
+        Ok(FrameRequest {
+            frame_id: frame_id,
+            game_result: game_result,
+            match_end: match_end,
+            player_frame_request: [me, op],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puyoai_core::color::RealColor;
+    use puyoai_core::field::RealPlainField;
+    use puyoai_core::kumipuyo::{Kumireal, KumipuyoPos};
+    use game_result::GameResult;
+    use user_event::UserEvent;
+
+    fn make_frame_request() -> FrameRequest {
+        let mut event = UserEvent::new();
+        event.decision_request = true;
+
+        let me = PlayerFrameRequest {
+            field: RealPlainField::from_str("RRBB.."),
+            seq: vec![Kumireal::new(RealColor::RED, RealColor::BLUE)],
+            pos: KumipuyoPos::initial_pos(),
+            event: event,
+            score: 40,
+            ojama: 0,
+        };
+        let op = PlayerFrameRequest {
+            field: RealPlainField::new(),
+            seq: vec![Kumireal::new(RealColor::YELLOW, RealColor::GREEN)],
+            pos: KumipuyoPos::new(0, 0, 0),
+            event: UserEvent::new(),
+            score: 0,
+            ojama: 6,
+        };
+
+        FrameRequest {
+            frame_id: 3,
+            game_result: GameResult::Playing,
+            match_end: false,
+            player_frame_request: [me, op],
+        }
+    }
+
+    #[test]
+    fn test_to_string() {
+        let req = make_frame_request();
+
+        let empty_rows = "......".repeat(12);
+        let expected = format!("ID=3 STATE={} YF={}RRBB.. OF={}...... YP=RB OP=YG \
+                                YX=3 YY=12 YR=0 OX=0 OY=0 OR=0 YS=40 OS=0 YO=0 OO=6",
+                               1 << 6, empty_rows, empty_rows);
+        assert_eq!(expected, req.to_string());
+    }
+
+    #[test]
+    fn test_parse() {
+        let req = FrameRequest::parse(&make_frame_request().to_string()).unwrap();
+
+        assert_eq!(3, req.frame_id);
+        assert_eq!(GameResult::Playing, req.game_result);
+        assert!(!req.match_end);
+
+        let me = &req.player_frame_request[0];
+        assert_eq!(field_to_string(&RealPlainField::from_str("RRBB..")), field_to_string(&me.field));
+        assert_eq!(vec![Kumireal::new(RealColor::RED, RealColor::BLUE)], me.seq);
+        assert_eq!(KumipuyoPos::initial_pos(), me.pos);
+        assert!(me.event.decision_request);
+        assert_eq!(40, me.score);
+
+        let op = &req.player_frame_request[1];
+        assert_eq!(field_to_string(&RealPlainField::new()), field_to_string(&op.field));
+        assert_eq!(UserEvent::new(), op.event);
+        assert_eq!(6, op.ojama);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut req = make_frame_request();
+        req.game_result = GameResult::P2Win;
+        req.match_end = true;
+        req.player_frame_request[1].event.ojama_dropped = true;
+
+        let s = req.to_string();
+        let parsed = FrameRequest::parse(&s).unwrap();
+        assert_eq!(GameResult::P2Win, parsed.game_result);
+        assert!(parsed.match_end);
+        assert_eq!(s, parsed.to_string());
+    }
+
+    #[test]
+    fn test_parse_purple() {
+        let req = FrameRequest::parse("ID=1 YF=PPR... YP=PBRP").unwrap();
+        let me = &req.player_frame_request[0];
+        assert_eq!(RealColor::PURPLE, me.field.color(1, 1));
+        assert_eq!(RealColor::RED, me.field.color(3, 1));
+        assert_eq!(vec![Kumireal::new(RealColor::PURPLE, RealColor::BLUE),
+                        Kumireal::new(RealColor::RED, RealColor::PURPLE)], me.seq);
+        let s = req.to_string();
+        assert!(s.contains("PPR... OF="));
+        assert!(s.contains(" YP=PBRP "));
+    }
+
+    #[test]
+    fn test_parse_minimal() {
+        let req = FrameRequest::parse("ID=10").unwrap();
+        assert_eq!(10, req.frame_id);
+        assert_eq!(GameResult::Playing, req.game_result);
+        assert!(req.player_frame_request[0].seq.is_empty());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(FrameRequest::parse("").is_err());
+        assert!(FrameRequest::parse("ID").is_err());
+        assert!(FrameRequest::parse("ID=x").is_err());
+        assert!(FrameRequest::parse("ID=1 END=100").is_err());
+        assert!(FrameRequest::parse("ID=1 YF=RRR").is_err());
+        assert!(FrameRequest::parse("ID=1 YF=RRRXXX").is_err());
+        assert!(FrameRequest::parse("ID=1 YF=RRR&&&").is_err());
+        assert!(FrameRequest::parse("ID=1 YP=RBY").is_err());
+        assert!(FrameRequest::parse("ID=1 YP=RO").is_err());
+        assert!(FrameRequest::parse("ID=1 YS=-1").is_err());
+    }
+}
@@ -1,27 +1,97 @@
+use puyoai_core::control::KeySet;
+use puyoai_core::control::key_set::{keysetseq_to_string, parse_keysetseq};
 use puyoai_core::decision::Decision;
 
+/// FrameResponse is sent from a client to the server.
+///
+/// ```text
+/// ID=1 X=3 R=1 PX=4 PR=0 K=>A,v DROPPED=1 MSG=hello_world
+/// ```
+///
+/// Only ID is required. PX and PR are the decision for the next kumipuyo.
+/// K overrides the keys the server presses: one KeySet per frame.
+/// DROPPED=1 tells the server that the client has skipped some frames.
 pub struct FrameResponse {
     pub frame_id: i32,
     pub decision: Decision,
+    pub next_decision: Option<Decision>,
+    pub key_set_seq: Vec<KeySet>,
+    pub dropped: bool,
     pub message: String,
 }
 
+// A space is written as '_' and a newline as ','. The literal '_', ',' and '\\'
+// are escaped with '\\', so that unescape_message restores the original message.
+// The other whitespace would split the term, so a tab is written as "\t", a CR as "\r",
+// and the rest as "\u" and 4 hex digits. All of them are in the BMP.
 fn escape_message(s: &str) -> String {
     let mut result = String::new();
     for c in s.chars() {
-        if c == ' ' {
-            result.push('_');
-        } else if c == '\n' {
-            result.push(',');
-        } else {
-            result.push(c);
+        match c {
+            ' ' => result.push('_'),
+            '\n' => result.push(','),
+            '\t' => result.push_str("\\t"),
+            '\r' => result.push_str("\\r"),
+            '_' | ',' | '\\' => {
+                result.push('\\');
+                result.push(c);
+            },
+            _ if c.is_whitespace() => result.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => result.push(c),
         }
     }
 
     result
 }
 
+fn unescape_message(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '_' => result.push(' '),
+            ',' => result.push('\n'),
+            '\\' => match chars.next() {
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    match u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32) {
+                        Some(u) if hex.len() == 4 => result.push(u),
+                        // A broken escape is kept as is.
+                        _ => {
+                            result.push_str("\\u");
+                            result.push_str(&hex);
+                        },
+                    }
+                },
+                Some(escaped) => result.push(escaped),
+                // A trailing '\\' is kept as is.
+                None => result.push('\\'),
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+fn parse_usize(key: &str, value: &str) -> Result<usize, String> {
+    value.parse::<usize>().map_err(|_| format!("invalid value for {}: {}", key, value))
+}
+
 impl FrameResponse {
+    pub fn new(frame_id: i32) -> FrameResponse {
+        FrameResponse {
+            frame_id: frame_id,
+            decision: Decision::new(0, 0),
+            next_decision: None,
+            key_set_seq: Vec::new(),
+            dropped: false,
+            message: String::new(),
+        }
+    }
+
     pub fn to_string(&self) -> String {
         let mut result = String::new();
 
@@ -30,27 +100,171 @@ impl FrameResponse {
             result.push_str(&format!(" X={}", self.decision.axis_x()));
             result.push_str(&format!(" R={}", self.decision.rot()));
         }
+        if let Some(ref next_decision) = self.next_decision {
+            if next_decision.is_valid() {
+                result.push_str(&format!(" PX={}", next_decision.axis_x()));
+                result.push_str(&format!(" PR={}", next_decision.rot()));
+            }
+        }
+        if !self.key_set_seq.is_empty() {
+            result.push_str(&format!(" K={}", keysetseq_to_string(&self.key_set_seq)));
+        }
+        if self.dropped {
+            result.push_str(" DROPPED=1");
+        }
         if !self.message.is_empty() {
             result.push_str(&format!(" MSG={}", escape_message(&self.message)))
         }
 
         result
     }
+
+    /// Parses a line written by `to_string`. Unknown keys are ignored.
+    /// An invalid decision is treated as an error, since the server cannot do anything with it.
+    pub fn parse(s: &str) -> Result<FrameResponse, String> {
+        let mut frame_id = None;
+        let mut resp = FrameResponse::new(0);
+        let (mut x, mut r) = (None, None);
+        let (mut px, mut pr) = (None, None);
+
+        for term in s.split_whitespace() {
+            let eq = match term.find('=') {
+                Some(eq) => eq,
+                None => return Err(format!("'=' is missing: {}", term)),
+            };
+            let (key, value) = (&term[..eq], &term[eq + 1..]);
+
+            match key {
+                "ID" => {
+                    frame_id = Some(try!(value.parse::<i32>()
+                                         .map_err(|_| format!("invalid value for ID: {}", value))));
+                },
+                "X" => x = Some(try!(parse_usize(key, value))),
+                "R" => r = Some(try!(parse_usize(key, value))),
+                "PX" => px = Some(try!(parse_usize(key, value))),
+                "PR" => pr = Some(try!(parse_usize(key, value))),
+                "K" => resp.key_set_seq = try!(parse_keysetseq(value)),
+                "DROPPED" => resp.dropped = value == "1",
+                "MSG" => resp.message = unescape_message(value),
+                _ => {},
+            }
+        }
+
+        resp.frame_id = try!(frame_id.ok_or("ID is missing".to_string()));
+
+        match (x, r) {
+            (Some(x), Some(r)) => {
+                resp.decision = Decision::new(x, r);
+                if !resp.decision.is_valid() {
+                    return Err(format!("invalid decision: X={} R={}", x, r));
+                }
+            },
+            (None, None) => {},
+            _ => return Err("both X and R are necessary".to_string()),
+        }
+
+        match (px, pr) {
+            (Some(px), Some(pr)) => {
+                let next_decision = Decision::new(px, pr);
+                if !next_decision.is_valid() {
+                    return Err(format!("invalid next decision: PX={} PR={}", px, pr));
+                }
+                resp.next_decision = Some(next_decision);
+            },
+            (None, None) => {},
+            _ => return Err("both PX and PR are necessary".to_string()),
+        }
+
+        Ok(resp)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::FrameResponse;
+    use puyoai_core::control::{Key, KeySet};
     use puyoai_core::decision::Decision;
 
     #[test]
     fn test_to_string() {
+        let mut resp = FrameResponse::new(1);
+        resp.decision = Decision::new(3, 1);
+        resp.message = "test".to_string();
+
+        assert_eq!(resp.to_string(), "ID=1 X=3 R=1 MSG=test");
+    }
+
+    #[test]
+    fn test_to_string_all() {
         let resp = FrameResponse {
-            frame_id: 1,
+            frame_id: 2,
             decision: Decision::new(3, 1),
-            message: "test".to_string(),
+            next_decision: Some(Decision::new(1, 0)),
+            key_set_seq: vec![KeySet::from_keys(&[Key::Right, Key::RightTurn]), KeySet::from_key(Key::Down)],
+            dropped: true,
+            message: "hello world\nbye".to_string(),
         };
 
-        assert_eq!(resp.to_string(), "ID=1 X=3 R=1 MSG=test");
+        assert_eq!(resp.to_string(), "ID=2 X=3 R=1 PX=1 PR=0 K=>A,v DROPPED=1 MSG=hello_world,bye");
+    }
+
+    #[test]
+    fn test_parse() {
+        let resp = FrameResponse::parse("ID=2 X=3 R=1 PX=1 PR=0 K=>A,v DROPPED=1 MSG=hello_world,bye").unwrap();
+        assert_eq!(2, resp.frame_id);
+        assert_eq!(Decision::new(3, 1), resp.decision);
+        assert_eq!(Some(Decision::new(1, 0)), resp.next_decision);
+        assert_eq!(vec![KeySet::from_keys(&[Key::Right, Key::RightTurn]), KeySet::from_key(Key::Down)],
+                   resp.key_set_seq);
+        assert!(resp.dropped);
+        assert_eq!("hello world\nbye", resp.message);
+
+        let resp = FrameResponse::parse("ID=5").unwrap();
+        assert_eq!(5, resp.frame_id);
+        assert!(!resp.decision.is_valid());
+        assert_eq!(None, resp.next_decision);
+        assert!(resp.key_set_seq.is_empty());
+        assert!(!resp.dropped);
+        assert_eq!("", resp.message);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(FrameResponse::parse("").is_err());
+        assert!(FrameResponse::parse("X=3 R=0").is_err());
+        assert!(FrameResponse::parse("ID=1 X").is_err());
+        assert!(FrameResponse::parse("ID=1 X=a R=0").is_err());
+        assert!(FrameResponse::parse("ID=1 X=3").is_err());
+        assert!(FrameResponse::parse("ID=1 X=1 R=3").is_err());
+        assert!(FrameResponse::parse("ID=1 PX=3").is_err());
+        assert!(FrameResponse::parse("ID=1 K=>Z").is_err());
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut resp = FrameResponse::new(1);
+        resp.message = "a_b,c d\ne\\f".to_string();
+        let s = resp.to_string();
+        assert_eq!("ID=1 MSG=a\\_b\\,c_d,e\\\\f", s);
+        assert_eq!(resp.message, FrameResponse::parse(&s).unwrap().message);
+    }
+
+    #[test]
+    fn test_message_round_trip_whitespace() {
+        let mut resp = FrameResponse::new(1);
+        resp.message = "a\tb\r\nc\u{3000}d\u{a0}e".to_string();
+        let s = resp.to_string();
+        assert_eq!("ID=1 MSG=a\\tb\\r,c\\u3000d\\u00a0e", s);
+
+        let parsed = FrameResponse::parse(&format!("{} X=3 R=0", s)).unwrap();
+        assert_eq!(resp.message, parsed.message);
+        assert_eq!(3, parsed.decision.axis_x());
+    }
+
+    #[test]
+    fn test_unescape_broken_message() {
+        assert_eq!("\\u12", FrameResponse::parse("ID=1 MSG=\\u12").unwrap().message);
+        assert_eq!("\\uzzzz", FrameResponse::parse("ID=1 MSG=\\uzzzz").unwrap().message);
+        assert_eq!("a\\", FrameResponse::parse("ID=1 MSG=a\\").unwrap().message);
     }
 }
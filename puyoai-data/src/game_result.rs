@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum GameResult {
     Terminated,
     Playing,
@@ -7,3 +8,74 @@ pub enum GameResult {
     P1ConnectionLost,
     P2ConnectionLost,
 }
+
+impl GameResult {
+    pub fn is_finished(&self) -> bool {
+        *self != GameResult::Playing
+    }
+
+    /// Returns the result seen from the other player.
+    /// A FrameRequest is always written from the viewpoint of the receiver,
+    /// so the server swaps the result when it sends it to the 2nd player.
+    pub fn swapped(&self) -> GameResult {
+        match *self {
+            GameResult::P1Win => GameResult::P2Win,
+            GameResult::P2Win => GameResult::P1Win,
+            GameResult::P1ConnectionLost => GameResult::P2ConnectionLost,
+            GameResult::P2ConnectionLost => GameResult::P1ConnectionLost,
+            x => x,
+        }
+    }
+
+    pub fn to_int(&self) -> i32 {
+        match *self {
+            GameResult::Playing => -1,
+            GameResult::Draw => 0,
+            GameResult::P1Win => 1,
+            GameResult::P2Win => 2,
+            GameResult::P1ConnectionLost => 3,
+            GameResult::P2ConnectionLost => 4,
+            GameResult::Terminated => 5,
+        }
+    }
+
+    pub fn from_int(v: i32) -> Option<GameResult> {
+        match v {
+            -1 => Some(GameResult::Playing),
+            0 => Some(GameResult::Draw),
+            1 => Some(GameResult::P1Win),
+            2 => Some(GameResult::P2Win),
+            3 => Some(GameResult::P1ConnectionLost),
+            4 => Some(GameResult::P2ConnectionLost),
+            5 => Some(GameResult::Terminated),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GameResult;
+
+    #[test]
+    fn test_swapped() {
+        assert_eq!(GameResult::P2Win, GameResult::P1Win.swapped());
+        assert_eq!(GameResult::P1Win, GameResult::P2Win.swapped());
+        assert_eq!(GameResult::P2ConnectionLost, GameResult::P1ConnectionLost.swapped());
+        assert_eq!(GameResult::Draw, GameResult::Draw.swapped());
+        assert_eq!(GameResult::Playing, GameResult::Playing.swapped());
+    }
+
+    #[test]
+    fn test_int() {
+        let results = [
+            GameResult::Terminated, GameResult::Playing, GameResult::Draw,
+            GameResult::P1Win, GameResult::P2Win,
+            GameResult::P1ConnectionLost, GameResult::P2ConnectionLost,
+        ];
+        for r in results.iter() {
+            assert_eq!(Some(*r), GameResult::from_int(r.to_int()));
+        }
+        assert_eq!(None, GameResult::from_int(6));
+    }
+}
@@ -0,0 +1,279 @@
+//! A minimal JSON reader and writer for the files written by puyoai.
+//! Only what the files need is supported, e.g. numbers are kept as their text.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    // The text of the number, so that a large integer does not lose precision.
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Returns the value for `key` if this is an object.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match *self {
+            JsonValue::Object(ref members) => {
+                members.iter().find(|m| m.0 == key).map(|m| &m.1)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            JsonValue::Number(ref s) => s.parse::<u64>().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            JsonValue::Number(ref s) => s.parse::<i64>().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            JsonValue::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match *self {
+            JsonValue::Array(ref values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == JsonValue::Null
+    }
+
+    pub fn to_string(&self) -> String {
+        match *self {
+            JsonValue::Null => "null".to_string(),
+            JsonValue::Bool(b) => b.to_string(),
+            JsonValue::Number(ref s) => s.clone(),
+            JsonValue::String(ref s) => quote(s),
+            JsonValue::Array(ref values) => {
+                let strs: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                format!("[{}]", strs.join(","))
+            },
+            JsonValue::Object(ref members) => {
+                let strs: Vec<String> = members.iter()
+                    .map(|m| format!("{}:{}", quote(&m.0), m.1.to_string()))
+                    .collect();
+                format!("{{{}}}", strs.join(","))
+            },
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<JsonValue, String> {
+        let mut chars = s.chars().peekable();
+        let value = try!(parse_value(&mut chars));
+        skip_whitespace(&mut chars);
+        if let Some(c) = chars.next() {
+            return Err(format!("unexpected character after the value: {}", c));
+        }
+        Ok(value)
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while let Some(&c) = chars.peek() {
+        if !c.is_whitespace() {
+            break;
+        }
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: &str) -> Result<(), String> {
+    for e in expected.chars() {
+        match chars.next() {
+            Some(c) if c == e => {},
+            _ => return Err(format!("{} is expected", expected)),
+        }
+    }
+    Ok(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<JsonValue, String> {
+    skip_whitespace(chars);
+    let c = match chars.peek() {
+        Some(&c) => c,
+        None => return Err("unexpected end of the input".to_string()),
+    };
+
+    match c {
+        'n' => expect(chars, "null").map(|_| JsonValue::Null),
+        't' => expect(chars, "true").map(|_| JsonValue::Bool(true)),
+        'f' => expect(chars, "false").map(|_| JsonValue::Bool(false)),
+        '"' => parse_string(chars).map(JsonValue::String),
+        '[' => parse_array(chars),
+        '{' => parse_object(chars),
+        '-' | '0' ... '9' => parse_number(chars),
+        _ => Err(format!("unexpected character: {}", c)),
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Result<JsonValue, String> {
+    let mut s = String::new();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '-' | '+' | '.' | 'e' | 'E' | '0' ... '9' => s.push(c),
+            _ => break,
+        }
+        chars.next();
+    }
+    if s.parse::<f64>().is_err() {
+        return Err(format!("invalid number: {}", s));
+    }
+    Ok(JsonValue::Number(s))
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    try!(expect(chars, "\""));
+    let mut result = String::new();
+    loop {
+        match chars.next() {
+            None => return Err("unterminated string".to_string()),
+            Some('"') => return Ok(result),
+            Some('\\') => {
+                match chars.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('/') => result.push('/'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32);
+                        match c {
+                            Some(c) => result.push(c),
+                            None => return Err(format!("invalid unicode escape: {}", hex)),
+                        }
+                    },
+                    _ => return Err("invalid escape".to_string()),
+                }
+            },
+            Some(c) => result.push(c),
+        }
+    }
+}
+
+fn parse_array(chars: &mut Peekable<Chars>) -> Result<JsonValue, String> {
+    try!(expect(chars, "["));
+    let mut values = Vec::new();
+    skip_whitespace(chars);
+    if chars.peek() == Some(&']') {
+        chars.next();
+        return Ok(JsonValue::Array(values));
+    }
+
+    loop {
+        values.push(try!(parse_value(chars)));
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => {},
+            Some(']') => return Ok(JsonValue::Array(values)),
+            _ => return Err("',' or ']' is expected".to_string()),
+        }
+    }
+}
+
+fn parse_object(chars: &mut Peekable<Chars>) -> Result<JsonValue, String> {
+    try!(expect(chars, "{"));
+    let mut members = Vec::new();
+    skip_whitespace(chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+        return Ok(JsonValue::Object(members));
+    }
+
+    loop {
+        skip_whitespace(chars);
+        let key = try!(parse_string(chars));
+        skip_whitespace(chars);
+        try!(expect(chars, ":"));
+        let value = try!(parse_value(chars));
+        members.push((key, value));
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => {},
+            Some('}') => return Ok(JsonValue::Object(members)),
+            _ => return Err("',' or '}' is expected".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::JsonValue;
+
+    #[test]
+    fn test_parse() {
+        let v = JsonValue::parse(r#" {"a": [1, -2.5, null, true], "b": "x\"y\n", "c": {}} "#).unwrap();
+        assert_eq!(Some(1), v.get("a").unwrap().as_array().unwrap()[0].as_u64());
+        assert_eq!(JsonValue::Number("-2.5".to_string()), v.get("a").unwrap().as_array().unwrap()[1]);
+        assert!(v.get("a").unwrap().as_array().unwrap()[2].is_null());
+        assert_eq!(Some("x\"y\n"), v.get("b").unwrap().as_str());
+        assert_eq!(Some(&JsonValue::Object(Vec::new())), v.get("c"));
+        assert_eq!(None, v.get("d"));
+    }
+
+    #[test]
+    fn test_large_integer() {
+        let v = JsonValue::parse("18446744073709551615").unwrap();
+        assert_eq!(Some(18446744073709551615), v.as_u64());
+    }
+
+    #[test]
+    fn test_to_string() {
+        let v = JsonValue::Object(vec![
+            ("a".to_string(), JsonValue::Array(vec![JsonValue::Number("1".to_string()), JsonValue::Null])),
+            ("b".to_string(), JsonValue::String("x\"y".to_string())),
+        ]);
+        assert_eq!(r#"{"a":[1,null],"b":"x\"y"}"#, v.to_string());
+        assert_eq!(v, JsonValue::parse(&v.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(JsonValue::parse("").is_err());
+        assert!(JsonValue::parse("[1,").is_err());
+        assert!(JsonValue::parse("{\"a\" 1}").is_err());
+        assert!(JsonValue::parse("nul").is_err());
+        assert!(JsonValue::parse("1 2").is_err());
+        assert!(JsonValue::parse("\"abc").is_err());
+    }
+}
@@ -0,0 +1,559 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use puyoai_core::color::{Color, RealColor};
+use puyoai_core::control::{Key, KeySet};
+use puyoai_core::control::key_set::parse_keysetseq;
+use puyoai_core::decision::Decision;
+use puyoai_core::kumipuyo::Kumireal;
+
+use game_result::GameResult;
+use json::JsonValue;
+
+pub const KIFU_VERSION: u64 = 1;
+
+const BINARY_MAGIC: &'static [u8] = b"PKIF";
+
+// The bit order of keys in the binary format.
+const ALL_KEYS: [Key; 7] = [Key::Up, Key::Right, Key::Down, Key::Left,
+                            Key::RightTurn, Key::LeftTurn, Key::Start];
+
+/// KifuFrame is what the players did in one frame.
+/// `decisions` are the decisions the server received in the frame, if any.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KifuFrame {
+    pub key_sets: [KeySet; 2],
+    pub decisions: [Option<Decision>; 2],
+}
+
+impl KifuFrame {
+    pub fn new() -> KifuFrame {
+        KifuFrame {
+            key_sets: [KeySet::new(), KeySet::new()],
+            decisions: [None, None],
+        }
+    }
+
+    /// Returns true if nothing happened in this frame.
+    pub fn is_idle(&self) -> bool {
+        *self == KifuFrame::new()
+    }
+}
+
+/// Kifu is the record of one game. The game can be replayed exactly from the rule,
+/// the sequences and the keys of every frame. frames[i] is the frame whose ID is i + 1.
+///
+/// A kifu is written either as JSON lines or as a compact binary. In JSON lines,
+/// idle frames are omitted.
+///
+/// ```text
+/// {"type":"header","version":1,"num_colors":4,"seed":12345,"seqs":["RBYG...","RBYG..."]}
+/// {"type":"frame","id":12,"keys":[">A",""],"decisions":[[4,1],null]}
+/// {"type":"result","result":1,"frames":1234}
+/// ```
+///
+/// The colors in seqs are RealColor, so PURPLE ('P') appears in 5 color games.
+/// The binary starts with "PKIF", and the frames are run-length encoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kifu {
+    pub num_colors: usize,
+    pub seed: u64,
+    pub seqs: [Vec<Kumireal>; 2],
+    pub frames: Vec<KifuFrame>,
+    pub result: GameResult,
+}
+
+impl Kifu {
+    pub fn new(num_colors: usize, seed: u64, seqs: [Vec<Kumireal>; 2]) -> Kifu {
+        Kifu {
+            num_colors: num_colors,
+            seed: seed,
+            seqs: seqs,
+            frames: Vec::new(),
+            result: GameResult::Playing,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Kifu, String> {
+        let mut bytes = Vec::new();
+        try!(File::open(path.as_ref())
+             .and_then(|mut f| f.read_to_end(&mut bytes))
+             .map_err(|e| format!("failed to read {}: {}", path.as_ref().display(), e)));
+
+        if bytes.starts_with(BINARY_MAGIC) {
+            return Kifu::parse_binary(&bytes);
+        }
+        match String::from_utf8(bytes) {
+            Ok(s) => Kifu::parse_json_lines(&s),
+            Err(_) => Err(format!("{} is neither JSON lines nor binary", path.as_ref().display())),
+        }
+    }
+
+    /// Saves the kifu as JSON lines if the extension of `path` is "jsonl",
+    /// and as the binary otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let bytes = if path.as_ref().extension().map_or(false, |e| e == "jsonl") {
+            self.to_json_lines().into_bytes()
+        } else {
+            self.to_binary()
+        };
+
+        File::create(path.as_ref())
+            .and_then(|mut f| f.write_all(&bytes))
+            .map_err(|e| format!("failed to write {}: {}", path.as_ref().display(), e))
+    }
+
+    pub fn to_json_lines(&self) -> String {
+        let mut result = String::new();
+
+        let header = JsonValue::Object(vec![
+            ("type".to_string(), JsonValue::String("header".to_string())),
+            ("version".to_string(), number(KIFU_VERSION)),
+            ("num_colors".to_string(), number(self.num_colors)),
+            ("seed".to_string(), number(self.seed)),
+            ("seqs".to_string(), JsonValue::Array(vec![
+                JsonValue::String(seq_to_string(&self.seqs[0])),
+                JsonValue::String(seq_to_string(&self.seqs[1])),
+            ])),
+        ]);
+        result.push_str(&header.to_string());
+        result.push('\n');
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            if frame.is_idle() {
+                continue;
+            }
+            let line = JsonValue::Object(vec![
+                ("type".to_string(), JsonValue::String("frame".to_string())),
+                ("id".to_string(), number(i + 1)),
+                ("keys".to_string(), JsonValue::Array(vec![
+                    JsonValue::String(frame.key_sets[0].to_string()),
+                    JsonValue::String(frame.key_sets[1].to_string()),
+                ])),
+                ("decisions".to_string(), JsonValue::Array(vec![
+                    decision_to_json(&frame.decisions[0]),
+                    decision_to_json(&frame.decisions[1]),
+                ])),
+            ]);
+            result.push_str(&line.to_string());
+            result.push('\n');
+        }
+
+        let footer = JsonValue::Object(vec![
+            ("type".to_string(), JsonValue::String("result".to_string())),
+            ("result".to_string(), JsonValue::Number(self.result.to_int().to_string())),
+            ("frames".to_string(), number(self.frames.len())),
+        ]);
+        result.push_str(&footer.to_string());
+        result.push('\n');
+
+        result
+    }
+
+    pub fn parse_json_lines(s: &str) -> Result<Kifu, String> {
+        let mut kifu: Option<Kifu> = None;
+        let mut finished = false;
+
+        for (i, line) in s.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let v = try!(JsonValue::parse(line).map_err(|e| format!("line {}: {}", i + 1, e)));
+            let ty = v.get("type").and_then(|t| t.as_str()).unwrap_or("");
+            let parsed = if kifu.is_none() {
+                if ty == "header" {
+                    parse_json_header(&v).map(|k| { kifu = Some(k); })
+                } else {
+                    Err("the header must come first".to_string())
+                }
+            } else if finished {
+                Err("no line can follow the result".to_string())
+            } else {
+                match ty {
+                    "frame" => parse_json_frame(&v, kifu.as_mut().unwrap()),
+                    "result" => {
+                        finished = true;
+                        parse_json_result(&v, kifu.as_mut().unwrap())
+                    },
+                    _ => Err(format!("unknown type: {}", ty)),
+                }
+            };
+            try!(parsed.map_err(|e| format!("line {}: {}", i + 1, e)));
+        }
+
+        if !finished {
+            return Err("the result is missing".to_string());
+        }
+        Ok(kifu.unwrap())
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(BINARY_MAGIC);
+        write_varint(&mut result, KIFU_VERSION);
+        write_varint(&mut result, self.num_colors as u64);
+        write_varint(&mut result, self.seed);
+        for seq in self.seqs.iter() {
+            write_varint(&mut result, seq.len() as u64);
+            result.extend_from_slice(seq_to_string(seq).as_bytes());
+        }
+        result.push((self.result.to_int() + 1) as u8);
+
+        write_varint(&mut result, self.frames.len() as u64);
+        let mut i = 0;
+        while i < self.frames.len() {
+            let mut run = 1;
+            while i + run < self.frames.len() && self.frames[i + run] == self.frames[i] {
+                run += 1;
+            }
+            write_varint(&mut result, run as u64);
+            let frame = &self.frames[i];
+            for p in 0..2 {
+                result.push(key_set_to_bits(&frame.key_sets[p]));
+                result.push(decision_to_byte(&frame.decisions[p]));
+            }
+            i += run;
+        }
+
+        result
+    }
+
+    pub fn parse_binary(bytes: &[u8]) -> Result<Kifu, String> {
+        if !bytes.starts_with(BINARY_MAGIC) {
+            return Err("not a binary kifu".to_string());
+        }
+        let mut reader = ByteReader { bytes: bytes, pos: BINARY_MAGIC.len() };
+
+        let version = try!(reader.read_varint());
+        if version != KIFU_VERSION {
+            return Err(format!("unsupported version: {}", version));
+        }
+        let num_colors = try!(reader.read_varint()) as usize;
+        let seed = try!(reader.read_varint());
+        let mut seqs = [Vec::new(), Vec::new()];
+        for p in 0..2 {
+            let len = try!(reader.read_varint()) as usize;
+            let s = try!(reader.read_bytes(len * 2));
+            seqs[p] = try!(parse_seq(&String::from_utf8_lossy(s)));
+        }
+        let result_byte = try!(reader.read_byte());
+        let result = try!(GameResult::from_int(result_byte as i32 - 1)
+                          .ok_or(format!("invalid result: {}", result_byte)));
+
+        let mut kifu = Kifu::new(num_colors, seed, seqs);
+        kifu.result = result;
+
+        let num_frames = try!(reader.read_varint()) as usize;
+        while kifu.frames.len() < num_frames {
+            let run = try!(reader.read_varint()) as usize;
+            if run == 0 || kifu.frames.len() + run > num_frames {
+                return Err(format!("invalid run length: {}", run));
+            }
+            let mut frame = KifuFrame::new();
+            for p in 0..2 {
+                frame.key_sets[p] = try!(key_set_from_bits(try!(reader.read_byte())));
+                frame.decisions[p] = try!(decision_from_byte(try!(reader.read_byte())));
+            }
+            for _ in 0..run {
+                kifu.frames.push(frame.clone());
+            }
+        }
+
+        if reader.pos != bytes.len() {
+            return Err("extra bytes after the frames".to_string());
+        }
+        Ok(kifu)
+    }
+}
+
+fn number<T: ToString>(n: T) -> JsonValue {
+    JsonValue::Number(n.to_string())
+}
+
+fn seq_to_string(seq: &[Kumireal]) -> String {
+    let mut result = String::new();
+    for kp in seq {
+        result.push(kp.axis().to_char());
+        result.push(kp.child().to_char());
+    }
+    result
+}
+
+fn parse_puyo_color(c: char) -> Result<RealColor, String> {
+    match c {
+        'R' | 'B' | 'Y' | 'G' | 'P' => Ok(RealColor::from_byte(c as u8)),
+        _ => Err(format!("invalid kumipuyo color: {}", c)),
+    }
+}
+
+fn parse_seq(s: &str) -> Result<Vec<Kumireal>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() % 2 != 0 {
+        return Err(format!("invalid kumipuyo sequence length: {}", chars.len()));
+    }
+
+    let mut seq = Vec::new();
+    for pair in chars.chunks(2) {
+        let axis = try!(parse_puyo_color(pair[0]));
+        let child = try!(parse_puyo_color(pair[1]));
+        seq.push(Kumireal::new(axis, child));
+    }
+    Ok(seq)
+}
+
+fn decision_to_json(decision: &Option<Decision>) -> JsonValue {
+    match *decision {
+        Some(ref d) => JsonValue::Array(vec![number(d.axis_x()), number(d.rot())]),
+        None => JsonValue::Null,
+    }
+}
+
+fn decision_from_json(v: &JsonValue) -> Result<Option<Decision>, String> {
+    if v.is_null() {
+        return Ok(None);
+    }
+    let xr = try!(v.as_array().ok_or("decision must be an array".to_string()));
+    if xr.len() != 2 {
+        return Err("decision must be [x, r]".to_string());
+    }
+    match (xr[0].as_u64(), xr[1].as_u64()) {
+        (Some(x), Some(r)) => {
+            let decision = Decision::new(x as usize, r as usize);
+            if !decision.is_valid() {
+                return Err(format!("invalid decision: [{}, {}]", x, r));
+            }
+            Ok(Some(decision))
+        },
+        _ => Err("decision must be [x, r]".to_string()),
+    }
+}
+
+fn get_u64(v: &JsonValue, key: &str) -> Result<u64, String> {
+    v.get(key).and_then(|x| x.as_u64()).ok_or(format!("{} is missing or invalid", key))
+}
+
+fn get_pair<'a>(v: &'a JsonValue, key: &str) -> Result<&'a [JsonValue], String> {
+    match v.get(key).and_then(|x| x.as_array()) {
+        Some(values) if values.len() == 2 => Ok(values),
+        _ => Err(format!("{} must be an array of 2 elements", key)),
+    }
+}
+
+fn parse_json_header(v: &JsonValue) -> Result<Kifu, String> {
+    let version = try!(get_u64(v, "version"));
+    if version != KIFU_VERSION {
+        return Err(format!("unsupported version: {}", version));
+    }
+    let num_colors = try!(get_u64(v, "num_colors")) as usize;
+    let seed = try!(get_u64(v, "seed"));
+
+    let seqs = try!(get_pair(v, "seqs"));
+    let mut parsed_seqs = [Vec::new(), Vec::new()];
+    for p in 0..2 {
+        let s = try!(seqs[p].as_str().ok_or("seqs must be strings".to_string()));
+        parsed_seqs[p] = try!(parse_seq(s));
+    }
+
+    Ok(Kifu::new(num_colors, seed, parsed_seqs))
+}
+
+fn parse_json_frame(v: &JsonValue, kifu: &mut Kifu) -> Result<(), String> {
+    let id = try!(get_u64(v, "id")) as usize;
+    if id <= kifu.frames.len() {
+        return Err(format!("frame {} is out of order", id));
+    }
+
+    let mut frame = KifuFrame::new();
+    let keys = try!(get_pair(v, "keys"));
+    let decisions = try!(get_pair(v, "decisions"));
+    for p in 0..2 {
+        let s = try!(keys[p].as_str().ok_or("keys must be strings".to_string()));
+        // A KeySet is a key set sequence of one element.
+        let key_set_seq = try!(parse_keysetseq(s));
+        if key_set_seq.len() != 1 {
+            return Err(format!("invalid keys: {}", s));
+        }
+        frame.key_sets[p] = key_set_seq[0];
+        frame.decisions[p] = try!(decision_from_json(&decisions[p]));
+    }
+
+    kifu.frames.resize(id - 1, KifuFrame::new());
+    kifu.frames.push(frame);
+    Ok(())
+}
+
+fn parse_json_result(v: &JsonValue, kifu: &mut Kifu) -> Result<(), String> {
+    let result = try!(v.get("result").and_then(|x| x.as_i64()).ok_or("result is missing".to_string()));
+    kifu.result = try!(GameResult::from_int(result as i32).ok_or(format!("invalid result: {}", result)));
+
+    let num_frames = try!(get_u64(v, "frames")) as usize;
+    if num_frames < kifu.frames.len() {
+        return Err(format!("frames is less than the last frame: {}", num_frames));
+    }
+    kifu.frames.resize(num_frames, KifuFrame::new());
+    Ok(())
+}
+
+fn key_set_to_bits(key_set: &KeySet) -> u8 {
+    let mut bits = 0;
+    for (i, k) in ALL_KEYS.iter().enumerate() {
+        if key_set.has_key(*k) {
+            bits |= 1 << i;
+        }
+    }
+    bits
+}
+
+fn key_set_from_bits(bits: u8) -> Result<KeySet, String> {
+    if bits >> ALL_KEYS.len() != 0 {
+        return Err(format!("invalid key bits: {}", bits));
+    }
+    let mut key_set = KeySet::new();
+    for (i, k) in ALL_KEYS.iter().enumerate() {
+        if bits & (1 << i) != 0 {
+            key_set.set_key(*k);
+        }
+    }
+    Ok(key_set)
+}
+
+// 0 means no decision. Since x >= 1, a valid decision is never 0.
+fn decision_to_byte(decision: &Option<Decision>) -> u8 {
+    match *decision {
+        Some(ref d) => ((d.axis_x() << 2) | d.rot()) as u8,
+        None => 0,
+    }
+}
+
+fn decision_from_byte(b: u8) -> Result<Option<Decision>, String> {
+    if b == 0 {
+        return Ok(None);
+    }
+    let decision = Decision::new((b >> 2) as usize, (b & 3) as usize);
+    if !decision.is_valid() {
+        return Err(format!("invalid decision byte: {}", b));
+    }
+    Ok(Some(decision))
+}
+
+// Writes `v` as LEB128.
+fn write_varint(bytes: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        bytes.push((v & 0x7F) as u8 | 0x80);
+        v >>= 7;
+    }
+    bytes.push(v as u8);
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn read_byte(&mut self) -> Result<u8, String> {
+        if self.pos >= self.bytes.len() {
+            return Err("unexpected end of the binary".to_string());
+        }
+        self.pos += 1;
+        Ok(self.bytes[self.pos - 1])
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.pos + len > self.bytes.len() {
+            return Err("unexpected end of the binary".to_string());
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len .. self.pos])
+    }
+
+    fn read_varint(&mut self) -> Result<u64, String> {
+        let mut v = 0;
+        let mut shift = 0;
+        loop {
+            let b = try!(self.read_byte());
+            if shift >= 64 {
+                return Err("too long varint".to_string());
+            }
+            v |= ((b & 0x7F) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+            shift += 7;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kifu, KifuFrame};
+    use game_result::GameResult;
+    use puyoai_core::color::RealColor;
+    use puyoai_core::control::{Key, KeySet};
+    use puyoai_core::decision::Decision;
+    use puyoai_core::kumipuyo::Kumireal;
+
+    fn make_kifu() -> Kifu {
+        let seq = vec![Kumireal::new(RealColor::RED, RealColor::BLUE),
+                       Kumireal::new(RealColor::YELLOW, RealColor::PURPLE)];
+        let mut kifu = Kifu::new(5, 18446744073709551615, [seq.clone(), seq]);
+
+        let mut frame = KifuFrame::new();
+        frame.key_sets[0] = KeySet::from_keys(&[Key::Right, Key::RightTurn]);
+        frame.decisions[1] = Some(Decision::new(1, 1));
+        kifu.frames.push(KifuFrame::new());
+        kifu.frames.push(frame);
+        for _ in 0..300 {
+            kifu.frames.push(KifuFrame::new());
+        }
+        kifu.frames.push(KifuFrame {
+            key_sets: [KeySet::new(), KeySet::from_key(Key::Down)],
+            decisions: [Some(Decision::new(6, 2)), None],
+        });
+        kifu.result = GameResult::P2Win;
+        kifu
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let kifu = make_kifu();
+        let s = kifu.to_json_lines();
+        assert_eq!(4, s.lines().count());
+        assert_eq!(r#"{"type":"frame","id":2,"keys":[">A",""],"decisions":[null,[1,1]]}"#,
+                   s.lines().nth(1).unwrap());
+        assert_eq!(r#"{"type":"result","result":2,"frames":303}"#, s.lines().nth(3).unwrap());
+        assert_eq!(kifu, Kifu::parse_json_lines(&s).unwrap());
+    }
+
+    #[test]
+    fn test_binary() {
+        let kifu = make_kifu();
+        let bytes = kifu.to_binary();
+        // The 300 idle frames are encoded in one run.
+        assert!(bytes.len() < 64);
+        assert_eq!(kifu, Kifu::parse_binary(&bytes).unwrap());
+
+        assert!(Kifu::parse_binary(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Kifu::parse_binary(b"PKIF").is_err());
+        assert!(Kifu::parse_binary(b"{}").is_err());
+    }
+
+    #[test]
+    fn test_parse_json_lines_error() {
+        let header = r#"{"type":"header","version":1,"num_colors":4,"seed":1,"seqs":["RB","RB"]}"#;
+        let result = r#"{"type":"result","result":1,"frames":3}"#;
+
+        assert!(Kifu::parse_json_lines(&format!("{}\n{}\n", header, result)).is_ok());
+        // The result is missing.
+        assert!(Kifu::parse_json_lines(header).is_err());
+        // The header is missing.
+        assert!(Kifu::parse_json_lines(result).is_err());
+        // The frames are out of order.
+        let frame = r#"{"type":"frame","id":2,"keys":["",""],"decisions":[null,null]}"#;
+        assert!(Kifu::parse_json_lines(&format!("{}\n{}\n{}\n{}\n", header, frame, frame, result)).is_err());
+        // Invalid decision.
+        let frame = r#"{"type":"frame","id":2,"keys":["",""],"decisions":[[1,3],null]}"#;
+        assert!(Kifu::parse_json_lines(&format!("{}\n{}\n{}\n", header, frame, result)).is_err());
+    }
+}
@@ -1,14 +1,18 @@
 //! puyoai-data defines FrameRequest, FrameResponse etc.
 //! They will be used for client-server communication.
+//! Kifu, the record of a game, is also defined here.
 
 extern crate puyoai_core;
 
 pub mod game_result;
 pub mod frame_request;
 pub mod frame_response;
+pub mod json;
+pub mod kifu;
 pub mod user_event;
 
 pub use game_result::GameResult;
-pub use frame_request::FrameRequest;
+pub use frame_request::{FrameRequest, PlayerFrameRequest};
 pub use frame_response::FrameResponse;
+pub use kifu::{Kifu, KifuFrame};
 pub use user_event::UserEvent;
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct UserEvent {
     pub wnext_appeared: bool,
     pub grounded: bool,
@@ -7,3 +8,75 @@ pub struct UserEvent {
     pub ojama_dropped: bool,
     pub puyo_erased: bool,
 }
+
+impl UserEvent {
+    pub fn new() -> UserEvent {
+        UserEvent {
+            wnext_appeared: false,
+            grounded: false,
+            pre_decision_request: false,
+            decision_request: false,
+            decision_request_again: false,
+            ojama_dropped: false,
+            puyo_erased: false,
+        }
+    }
+
+    /// Returns the events as bit flags. The i-th flag (in the field order) is at bit i.
+    pub fn to_bits(&self) -> u32 {
+        let flags = [
+            self.wnext_appeared,
+            self.grounded,
+            self.pre_decision_request,
+            self.decision_request,
+            self.decision_request_again,
+            self.ojama_dropped,
+            self.puyo_erased,
+        ];
+
+        let mut bits = 0;
+        for (i, flag) in flags.iter().enumerate() {
+            if *flag {
+                bits |= 1 << i;
+            }
+        }
+        bits
+    }
+
+    pub fn from_bits(bits: u32) -> UserEvent {
+        let flag = |i: usize| -> bool { (bits & (1 << i)) != 0 };
+        UserEvent {
+            wnext_appeared: flag(0),
+            grounded: flag(1),
+            pre_decision_request: flag(2),
+            decision_request: flag(3),
+            decision_request_again: flag(4),
+            ojama_dropped: flag(5),
+            puyo_erased: flag(6),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserEvent;
+
+    #[test]
+    fn test_to_bits() {
+        let mut event = UserEvent::new();
+        assert_eq!(0, event.to_bits());
+
+        event.wnext_appeared = true;
+        event.decision_request = true;
+        assert_eq!((1 << 0) | (1 << 3), event.to_bits());
+    }
+
+    #[test]
+    fn test_from_bits() {
+        let mut event = UserEvent::new();
+        event.grounded = true;
+        event.puyo_erased = true;
+        assert_eq!(event, UserEvent::from_bits(event.to_bits()));
+        assert_eq!(UserEvent::new(), UserEvent::from_bits(0));
+    }
+}
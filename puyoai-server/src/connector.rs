@@ -0,0 +1,107 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// Connector is a line-based connection to a client.
+pub trait Connector {
+    /// Returns false after the client has closed the connection.
+    fn is_alive(&self) -> bool;
+
+    /// Sends `line` to the client. Returns false if the client is not alive.
+    fn write_line(&mut self, line: &str) -> bool;
+
+    /// Returns a line if the client has already written one.
+    fn try_read_line(&mut self) -> Option<String>;
+
+    /// Waits for a line at most `timeout`.
+    fn read_line_with_timeout(&mut self, timeout: Duration) -> Option<String>;
+}
+
+/// PipeConnector talks with an AI client process via its stdin/stdout.
+/// Lines from the client are read in another thread, so reading never blocks the game.
+pub struct PipeConnector {
+    child: Child,
+    stdin: ChildStdin,
+    receiver: Receiver<String>,
+    alive: bool,
+}
+
+impl PipeConnector {
+    pub fn spawn(program: &str) -> Result<PipeConnector, String> {
+        let mut child = try!(Command::new(program)
+                             .stdin(Stdio::piped())
+                             .stdout(Stdio::piped())
+                             .stderr(Stdio::inherit())
+                             .spawn()
+                             .map_err(|e| format!("failed to spawn {}: {}", program, e)));
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let reader = BufReader::new(stdout);
+            for line in reader.lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+
+        Ok(PipeConnector {
+            child: child,
+            stdin: stdin,
+            receiver: receiver,
+            alive: true,
+        })
+    }
+}
+
+impl Connector for PipeConnector {
+    fn is_alive(&self) -> bool {
+        self.alive
+    }
+
+    fn write_line(&mut self, line: &str) -> bool {
+        if writeln!(self.stdin, "{}", line).is_err() || self.stdin.flush().is_err() {
+            self.alive = false;
+        }
+        self.alive
+    }
+
+    fn try_read_line(&mut self) -> Option<String> {
+        match self.receiver.try_recv() {
+            Ok(line) => Some(line),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.alive = false;
+                None
+            },
+        }
+    }
+
+    fn read_line_with_timeout(&mut self, timeout: Duration) -> Option<String> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(line) => Some(line),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => {
+                self.alive = false;
+                None
+            },
+        }
+    }
+}
+
+impl Drop for PipeConnector {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,572 @@
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use puyoai_core::color::RealColor;
+use puyoai_core::control::{Key, KeySet, PuyoController};
+use puyoai_core::decision::Decision;
+use puyoai_core::frame;
+use puyoai_core::field::FieldIsEmpty;
+use puyoai_core::kumipuyo::{KumipuyoMovingState, KumipuyoPos, kumipuyo_seq};
+use puyoai_data::{FrameRequest, FrameResponse, GameResult, Kifu, KifuFrame, PlayerFrameRequest, UserEvent};
+
+use connector::Connector;
+use field_realtime::FieldRealtime;
+use frame_context::FrameContext;
+
+// In lockstep, a client which doesn't answer a request in this time is not waited for in the frame.
+const LOCKSTEP_TIMEOUT_SECS: u64 = 10;
+// direct_key_set_seq gives up after this many frames.
+const MAX_DIRECT_FRAMES: usize = 10 * frame::FPS;
+
+/// DuelMode is how the frames of a Duel proceed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuelMode {
+    /// A frame takes 1/FPS seconds as in the real game. For human play and visual debugging.
+    Realtime,
+    /// The next frame starts as soon as both clients answer the request of the current frame.
+    /// So a client must answer every request, with only ID if it has nothing to say.
+    Lockstep,
+    /// Lockstep, and in addition the frames where no input matters, i.e. no field is playable
+    /// and nothing happens, are played without sending requests.
+    FastForward,
+}
+
+/// Duel runs one game between two AI clients, and records it as a Kifu.
+pub struct Duel<C: Connector> {
+    connectors: [C; 2],
+    fields: [FieldRealtime; 2],
+    decisions: [Option<Decision>; 2],
+    next_decisions: [Option<Decision>; 2],
+    key_set_seqs: [VecDeque<KeySet>; 2],
+    // True if key_set_seqs has been made for the current decision.
+    key_set_planned: [bool; 2],
+    controller: PuyoController,
+    decision_request_frame_ids: [i32; 2],
+    frame_id: i32,
+    mode: DuelMode,
+    kifu: Kifu,
+    // What the players do in the current frame. This is added to kifu when the frame is played.
+    kifu_frame: KifuFrame,
+}
+
+impl<C: Connector> Duel<C> {
+    /// Makes a game whose kumipuyo sequence is generated from `num_colors` and `seed`.
+    /// `num_colors` is 3, 4 or 5. The game is played with RealColor, so PURPLE is the 5th color.
+    pub fn new(p1: C, p2: C, num_colors: usize, seed: usize) -> Duel<C> {
+        let seq = kumipuyo_seq::generate_ac_sequence_with_seed::<RealColor>(num_colors, seed);
+        Duel {
+            connectors: [p1, p2],
+            fields: [FieldRealtime::new(0, &seq), FieldRealtime::new(1, &seq)],
+            decisions: [None, None],
+            next_decisions: [None, None],
+            key_set_seqs: [VecDeque::new(), VecDeque::new()],
+            key_set_planned: [false, false],
+            controller: PuyoController::new(),
+            decision_request_frame_ids: [0, 0],
+            frame_id: 0,
+            mode: DuelMode::Realtime,
+            kifu: Kifu::new(num_colors, seed as u64, [seq.clone(), seq]),
+            kifu_frame: KifuFrame::new(),
+        }
+    }
+
+    pub fn frame_id(&self) -> i32 {
+        self.frame_id
+    }
+
+    /// Sets how the frames proceed. The default is DuelMode::Realtime.
+    pub fn set_mode(&mut self, mode: DuelMode) {
+        self.mode = mode;
+    }
+
+    /// Returns the longest chain `player_id` has fired in this game.
+    pub fn max_chain(&self, player_id: usize) -> usize {
+        self.fields[player_id].max_chain()
+    }
+
+    /// Returns the record of the frames played so far.
+    pub fn kifu(&self) -> &Kifu {
+        &self.kifu
+    }
+
+    /// Runs the game until it ends, and returns the result.
+    pub fn run(&mut self) -> GameResult {
+        // In realtime, each frame ends at its deadline from the start, so neither the rounding of
+        // 1/FPS seconds nor a slow frame or a long sleep accumulates.
+        let frame_duration = Duration::new(0, 1_000_000_000 / frame::FPS as u32);
+        let start = Instant::now();
+        let mut num_realtime_frames = 0;
+
+        loop {
+            self.frame_id += 1;
+
+            let result = self.game_result();
+            let skipped = !result.is_finished() && self.mode == DuelMode::FastForward && is_idle_frame(&self.fields);
+            if !skipped {
+                for i in 0..2 {
+                    let req = self.make_frame_request(i, result);
+                    self.connectors[i].write_line(&req.to_string());
+                }
+            }
+            if result.is_finished() {
+                self.kifu.result = result;
+                return result;
+            }
+
+            if self.mode == DuelMode::Realtime {
+                num_realtime_frames += 1;
+                let deadline = start + frame_duration * num_realtime_frames;
+                let now = Instant::now();
+                if now < deadline {
+                    thread::sleep(deadline - now);
+                }
+            } else if !skipped {
+                for i in 0..2 {
+                    self.wait_for_response(i);
+                }
+            }
+
+            for i in 0..2 {
+                while let Some(line) = self.connectors[i].try_read_line() {
+                    if let Ok(resp) = FrameResponse::parse(&line) {
+                        self.handle_response(i, resp);
+                    }
+                }
+            }
+
+            let mut key_sets = [KeySet::new(), KeySet::new()];
+            for i in 0..2 {
+                self.plan_key_set_seq(i);
+                key_sets[i] = if !self.fields[i].is_playable() {
+                    KeySet::new()
+                } else if let Some(key_set) = self.key_set_seqs[i].pop_front() {
+                    key_set
+                } else {
+                    match self.decisions[i] {
+                        Some(ref decision) => next_key_set(&self.fields[i].kumipuyo_pos(), decision),
+                        None => KeySet::new(),
+                    }
+                };
+            }
+            play_frame(&mut self.fields, &key_sets);
+            self.kifu_frame.key_sets = key_sets;
+            self.kifu.frames.push(self.kifu_frame.clone());
+            self.kifu_frame = KifuFrame::new();
+
+            for i in 0..2 {
+                if self.fields[i].user_event().grounded {
+                    // The decision for the next kumipuyo is used until the client sends a new one.
+                    self.decisions[i] = self.next_decisions[i].take();
+                    self.key_set_seqs[i].clear();
+                    self.key_set_planned[i] = false;
+                }
+                if self.fields[i].user_event().decision_request {
+                    // The request will be sent in the next frame.
+                    self.decision_request_frame_ids[i] = self.frame_id + 1;
+                }
+            }
+        }
+    }
+
+    // Waits until `player_id` answers the request of the current frame.
+    // Responses to older requests are handled as usual while waiting.
+    fn wait_for_response(&mut self, player_id: usize) {
+        let deadline = Instant::now() + Duration::from_secs(LOCKSTEP_TIMEOUT_SECS);
+        while self.connectors[player_id].is_alive() {
+            let now = Instant::now();
+            if deadline <= now {
+                return;
+            }
+            let line = match self.connectors[player_id].read_line_with_timeout(deadline - now) {
+                Some(line) => line,
+                None => continue,
+            };
+            if let Ok(resp) = FrameResponse::parse(&line) {
+                let answered = resp.frame_id >= self.frame_id;
+                self.handle_response(player_id, resp);
+                if answered {
+                    return;
+                }
+            }
+        }
+    }
+
+    fn handle_response(&mut self, player_id: usize, resp: FrameResponse) {
+        // Ignore responses to the requests before the latest decision request.
+        if resp.frame_id < self.decision_request_frame_ids[player_id] {
+            return;
+        }
+
+        if resp.decision.is_valid() {
+            self.kifu_frame.decisions[player_id] = Some(resp.decision.clone());
+            self.decisions[player_id] = Some(resp.decision);
+            self.next_decisions[player_id] = resp.next_decision;
+            self.key_set_seqs[player_id].clear();
+            self.key_set_planned[player_id] = false;
+        }
+        if !resp.key_set_seq.is_empty() {
+            self.key_set_seqs[player_id] = resp.key_set_seq.into_iter().collect();
+            self.key_set_planned[player_id] = true;
+        }
+    }
+
+    // Makes the key set sequence to the current decision once per decision.
+    // Pressing next_key_set every frame is enough unless the kumipuyo has to climb or go around,
+    // and it costs little in the frame, so the search runs only when it fails.
+    // When the decision is unreachable, next_key_set is used instead.
+    fn plan_key_set_seq(&mut self, player_id: usize) {
+        if self.key_set_planned[player_id] || !self.fields[player_id].is_playable() {
+            return;
+        }
+        let decision = match self.decisions[player_id] {
+            Some(ref decision) => decision.clone(),
+            None => return,
+        };
+
+        self.key_set_planned[player_id] = true;
+        let fr = &self.fields[player_id];
+        let key_set_seq = match direct_key_set_seq(fr.field(), fr.moving_state(), &decision) {
+            Some(key_set_seq) => Some(key_set_seq),
+            None => self.controller.find_key_set_seq_from(fr.field(), fr.moving_state(), &decision),
+        };
+        if let Some(key_set_seq) = key_set_seq {
+            self.key_set_seqs[player_id] = key_set_seq.into_iter().collect();
+        }
+    }
+
+    fn game_result(&self) -> GameResult {
+        let p1_lost = !self.connectors[0].is_alive();
+        let p2_lost = !self.connectors[1].is_alive();
+        if p1_lost && p2_lost {
+            return GameResult::Draw;
+        }
+        if p1_lost {
+            return GameResult::P1ConnectionLost;
+        }
+        if p2_lost {
+            return GameResult::P2ConnectionLost;
+        }
+
+        game_result_of_fields(&self.fields)
+    }
+
+    fn make_player_frame_request(&self, player_id: usize) -> PlayerFrameRequest {
+        let fr = &self.fields[player_id];
+        PlayerFrameRequest {
+            field: fr.field().clone(),
+            seq: fr.visible_seq(),
+            pos: fr.kumipuyo_pos(),
+            event: *fr.user_event(),
+            score: fr.score() as u32,
+            ojama: fr.num_ojama() as u32,
+        }
+    }
+
+    // Makes a FrameRequest from the viewpoint of `player_id`.
+    fn make_frame_request(&self, player_id: usize, result: GameResult) -> FrameRequest {
+        let me = self.make_player_frame_request(player_id);
+        let op = self.make_player_frame_request(1 - player_id);
+        FrameRequest {
+            frame_id: self.frame_id,
+            game_result: if player_id == 0 { result } else { result.swapped() },
+            match_end: false,
+            player_frame_request: [me, op],
+        }
+    }
+}
+
+/// Plays one frame of both players with `key_sets`, and then exchanges the ojama.
+/// Duel and KifuPlayer share this, so that a kifu is replayed exactly.
+pub fn play_frame(fields: &mut [FieldRealtime; 2], key_sets: &[KeySet; 2]) {
+    let mut contexts = [FrameContext::new(), FrameContext::new()];
+    for i in 0..2 {
+        fields[i].play_one_frame(&key_sets[i], &mut contexts[i]);
+    }
+
+    for i in 0..2 {
+        let num_ojama = contexts[i].num_sent_ojama();
+        if num_ojama > 0 {
+            fields[1 - i].add_pending_ojama(num_ojama);
+        }
+        if contexts[i].is_ojama_committed() {
+            fields[1 - i].commit_ojama();
+        }
+    }
+}
+
+/// Returns true if no input matters in the next frame: no field is playable,
+/// and no event, which the clients need to know, has happened in the last frame.
+pub fn is_idle_frame(fields: &[FieldRealtime; 2]) -> bool {
+    fields.iter().all(|fr| !fr.is_playable() && *fr.user_event() == UserEvent::new())
+}
+
+/// Returns the result decided by the fields, i.e. whether a player is dead.
+pub fn game_result_of_fields(fields: &[FieldRealtime; 2]) -> GameResult {
+    match (fields[0].is_dead(), fields[1].is_dead()) {
+        (true, true) => GameResult::Draw,
+        (true, false) => GameResult::P2Win,
+        (false, true) => GameResult::P1Win,
+        (false, false) => GameResult::Playing,
+    }
+}
+
+// Returns the keys to move the kumipuyo at `pos` toward `decision`.
+// When the kumipuyo has reached there, it is dropped.
+fn next_key_set(pos: &KumipuyoPos, decision: &Decision) -> KeySet {
+    let x = pos.axis_x() as usize;
+    let r = pos.rot() as usize;
+    if x == decision.axis_x() && r == decision.rot() {
+        return KeySet::from_key(Key::Down);
+    }
+
+    let mut key_set = KeySet::new();
+    if r != decision.rot() {
+        if (r + 3) % 4 == decision.rot() {
+            key_set.set_key(Key::LeftTurn);
+        } else {
+            key_set.set_key(Key::RightTurn);
+        }
+    }
+    if x < decision.axis_x() {
+        key_set.set_key(Key::Right);
+    } else if decision.axis_x() < x {
+        key_set.set_key(Key::Left);
+    }
+
+    key_set
+}
+
+// Returns the key sets of next_key_set from `state` frame by frame, if the kumipuyo is
+// grounded at `decision` with them.
+fn direct_key_set_seq<F: FieldIsEmpty>(field: &F, state: &KumipuyoMovingState,
+                                       decision: &Decision) -> Option<Vec<KeySet>> {
+    let mut state = *state;
+    let mut key_set_seq = Vec::new();
+    // Turns could kick the kumipuyo up forever, so give up after a while.
+    while !state.grounded && key_set_seq.len() < MAX_DIRECT_FRAMES {
+        let key_set = next_key_set(&state.pos, decision);
+        state.move_kumipuyo(field, &key_set);
+        key_set_seq.push(key_set);
+    }
+
+    let pos = &state.pos;
+    if state.grounded && pos.axis_x() as usize == decision.axis_x() && pos.rot() as usize == decision.rot() {
+        Some(key_set_seq)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Duel, DuelMode, direct_key_set_seq, is_idle_frame, next_key_set, play_frame};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+    use connector::Connector;
+    use field_realtime::FieldRealtime;
+    use puyoai_core::control::{Key, KeySet, PuyoController};
+    use puyoai_core::decision::Decision;
+    use puyoai_core::field::PuyoPlainField;
+    use puyoai_core::frame;
+    use puyoai_core::kumipuyo::{KumipuyoMovingState, KumipuyoPos, kumipuyo_seq};
+    use puyoai_data::{FrameRequest, GameResult};
+
+    // What FakeConnector has seen. This is shared with the test, since Duel owns the connector.
+    struct FakeLog {
+        request_ids: Vec<i32>,
+        decision_request_ids: Vec<i32>,
+        // The number of the requests sent while the answer to the previous one was unread.
+        num_unanswered: usize,
+        num_waits: usize,
+    }
+
+    // FakeConnector is a scripted client which drops every kumipuyo on the 3rd column.
+    // It answers every request, but the answer can be read only by waiting for it,
+    // as if the client took a while to think. It closes the connection after `lifetime` requests.
+    struct FakeConnector {
+        lifetime: usize,
+        answers: VecDeque<String>,
+        log: Rc<RefCell<FakeLog>>,
+    }
+
+    impl FakeConnector {
+        fn new(lifetime: usize) -> (FakeConnector, Rc<RefCell<FakeLog>>) {
+            let log = Rc::new(RefCell::new(FakeLog {
+                request_ids: Vec::new(),
+                decision_request_ids: Vec::new(),
+                num_unanswered: 0,
+                num_waits: 0,
+            }));
+            let connector = FakeConnector {
+                lifetime: lifetime,
+                answers: VecDeque::new(),
+                log: log.clone(),
+            };
+            (connector, log)
+        }
+    }
+
+    impl Connector for FakeConnector {
+        fn is_alive(&self) -> bool {
+            self.lifetime > 0
+        }
+
+        fn write_line(&mut self, line: &str) -> bool {
+            if !self.is_alive() {
+                return false;
+            }
+            self.lifetime -= 1;
+
+            let req = FrameRequest::parse(line).unwrap();
+            let mut log = self.log.borrow_mut();
+            log.request_ids.push(req.frame_id);
+            if !self.answers.is_empty() {
+                log.num_unanswered += 1;
+            }
+            if req.game_result.is_finished() {
+                return true;
+            }
+
+            let event = req.player_frame_request[0].event;
+            if event.decision_request || event.decision_request_again {
+                log.decision_request_ids.push(req.frame_id);
+                // The keys are sent too, so that the server doesn't search them.
+                self.answers.push_back(format!("ID={} X=3 R=0 K=v", req.frame_id));
+            } else {
+                self.answers.push_back(format!("ID={}", req.frame_id));
+            }
+            true
+        }
+
+        fn try_read_line(&mut self) -> Option<String> {
+            None
+        }
+
+        fn read_line_with_timeout(&mut self, _timeout: Duration) -> Option<String> {
+            self.log.borrow_mut().num_waits += 1;
+            self.answers.pop_front()
+        }
+    }
+
+    // Both players die at the same time.
+    fn run_duel(mode: DuelMode) -> (Duel<FakeConnector>, GameResult, [Rc<RefCell<FakeLog>>; 2]) {
+        let (p1, log1) = FakeConnector::new(100000);
+        let (p2, log2) = FakeConnector::new(100000);
+        let mut duel = Duel::new(p1, p2, 4, 1);
+        duel.set_mode(mode);
+        let result = duel.run();
+        (duel, result, [log1, log2])
+    }
+
+    #[test]
+    fn test_lockstep() {
+        let (duel, result, logs) = run_duel(DuelMode::Lockstep);
+        assert_eq!(GameResult::Draw, result);
+
+        for log in logs.iter() {
+            let log = log.borrow();
+            // Every frame is requested, and answered before the next frame.
+            let expected_ids: Vec<i32> = (1 .. duel.frame_id() + 1).collect();
+            assert_eq!(expected_ids, log.request_ids);
+            assert_eq!(0, log.num_unanswered);
+            // The request of the last frame has the result, which is not answered.
+            assert_eq!(log.request_ids.len() - 1, log.num_waits);
+        }
+
+        // The decision is used in the frame where it was requested.
+        for &id in &logs[0].borrow().decision_request_ids {
+            assert_eq!(Some(Decision::new(3, 0)), duel.kifu().frames[id as usize - 1].decisions[0]);
+        }
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        let (lockstep, _, _) = run_duel(DuelMode::Lockstep);
+        let (duel, result, logs) = run_duel(DuelMode::FastForward);
+        assert_eq!(GameResult::Draw, result);
+
+        for log in logs.iter() {
+            let log = log.borrow();
+            // The idle frames are not requested, and the others are answered before the next frame.
+            assert!(log.request_ids.len() < duel.frame_id() as usize);
+            assert_eq!(0, log.num_unanswered);
+            assert_eq!(log.request_ids.len() - 1, log.num_waits);
+        }
+
+        // Skipping the idle frames doesn't change the game.
+        assert_eq!(lockstep.frame_id(), duel.frame_id());
+        assert_eq!(lockstep.kifu(), duel.kifu());
+    }
+
+    #[test]
+    fn test_realtime() {
+        // Player 1 closes the connection after 3 frames.
+        let (p1, log1) = FakeConnector::new(3);
+        let (p2, log2) = FakeConnector::new(100000);
+        let mut duel = Duel::new(p1, p2, 4, 1);
+        let start = Instant::now();
+        assert_eq!(GameResult::P1ConnectionLost, duel.run());
+        // The 3 frames before the result took 1/FPS seconds each.
+        assert!(start.elapsed() >= Duration::new(0, 3 * (1_000_000_000 / frame::FPS as u32)));
+
+        // The server never waits for the clients.
+        assert_eq!(0, log1.borrow().num_waits);
+        assert_eq!(0, log2.borrow().num_waits);
+        assert_eq!(vec![1, 2, 3, 4], log2.borrow().request_ids);
+    }
+
+    #[test]
+    fn test_next_key_set() {
+        let pos = KumipuyoPos::initial_pos();
+        assert_eq!(KeySet::from_key(Key::Down), next_key_set(&pos, &Decision::new(3, 0)));
+        assert_eq!(KeySet::from_keys(&[Key::Left, Key::RightTurn]), next_key_set(&pos, &Decision::new(1, 1)));
+        assert_eq!(KeySet::from_keys(&[Key::Right, Key::LeftTurn]), next_key_set(&pos, &Decision::new(5, 3)));
+        assert_eq!(KeySet::from_key(Key::RightTurn), next_key_set(&pos, &Decision::new(3, 2)));
+    }
+
+    #[test]
+    fn test_direct_key_set_seq() {
+        let state = KumipuyoMovingState::initial();
+
+        let field = PuyoPlainField::new();
+        for d in &[Decision::new(1, 1), Decision::new(3, 2), Decision::new(6, 0)] {
+            let key_set_seq = direct_key_set_seq(&field, &state, d).unwrap();
+            let mut s = state;
+            for key_set in &key_set_seq {
+                assert!(!s.grounded);
+                s.move_kumipuyo(&field, key_set);
+            }
+            assert!(s.grounded);
+            assert_eq!((d.axis_x(), d.rot()), (s.pos.axis_x() as usize, s.pos.rot() as usize));
+        }
+
+        // The kumipuyo has to climb onto the 1st column, which next_key_set doesn't do.
+        let field = PuyoPlainField::from_str(&("O....O".to_string() + &"OOOOOO".repeat(11)));
+        assert_eq!(None, direct_key_set_seq(&field, &state, &Decision::new(1, 0)));
+        assert!(PuyoController::new().find_key_set_seq(&field, &Decision::new(1, 0)).is_some());
+    }
+
+    #[test]
+    fn test_is_idle_frame() {
+        let seq = kumipuyo_seq::generate_ac_sequence_with_seed(4, 1);
+        let mut fields = [FieldRealtime::new(0, &seq), FieldRealtime::new(1, &seq)];
+
+        // Nothing can be done while NEXT is moving to the field.
+        assert!(is_idle_frame(&fields));
+
+        let mut num_idle_frames = 0;
+        while is_idle_frame(&fields) {
+            play_frame(&mut fields, &[KeySet::new(), KeySet::new()]);
+            num_idle_frames += 1;
+            assert!(num_idle_frames < 100);
+        }
+        assert!(fields[0].user_event().decision_request);
+
+        // The kumipuyo is controllable.
+        play_frame(&mut fields, &[KeySet::new(), KeySet::new()]);
+        assert!(!is_idle_frame(&fields));
+    }
+}
@@ -1,15 +1,648 @@
-use puyoai_core::kumipuyo::Kumipuyo;
+use std::cmp;
 
+use puyoai_core::color::RealColor;
+use puyoai_core::control::KeySet;
+use puyoai_core::field::{self, RealPlainField};
+use puyoai_core::frame;
+use puyoai_core::kumipuyo::{KumipuyoMovingState, KumipuyoPos, Kumireal};
+use puyoai_core::score;
+use puyoai_data::UserEvent;
+
+use frame_context::FrameContext;
+
+// At most 5 lines of ojama drop at once.
+const MAX_OJAMA_DROP: usize = 30;
+
+// The columns where the ojama less than 6 drop. The order continues over turns.
+const OJAMA_COLUMN_ORDER: [usize; 6] = [1, 4, 2, 5, 3, 6];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SimulationState {
+    // NEXT is moving to the field.
+    Preparing,
+    // The kumipuyo is controllable.
+    Playable,
+    // Puyos are falling after chigiri or vanishing.
+    Dropping,
+    Grounding,
+    Vanishing,
+    OjamaDropping,
+    Dead,
+}
+
+/// FieldRealtime is the frame-accurate game state of one player.
 pub struct FieldRealtime {
     player_id: usize,
-    kumipuyo_seq: Vec<Kumipuyo>,
+    field: RealPlainField,
+    kumipuyo_seq: Vec<Kumireal>,
+    seq_index: usize,
+    state: SimulationState,
+    sleep_for: usize,
+    moving: KumipuyoMovingState,
+    frames_playable: usize,
+    wnext_visible: bool,
+    current_chain: usize,
+    // The longest chain in this game.
+    max_chain: usize,
+    score: usize,
+    // The score which has not been converted to ojama yet.
+    score_carry: usize,
+    // Ojama sent by the opponent while the opponent's rensa is in progress.
+    num_pending_ojama: usize,
+    // Ojama which will drop when the current turn ends.
+    num_fixed_ojama: usize,
+    ojama_column_index: usize,
+    zenkeshi: bool,
+    user_event: UserEvent,
 }
 
 impl FieldRealtime {
-    pub fn new(player_id: usize, seq: &[Kumipuyo]) -> FieldRealtime {
+    pub fn new(player_id: usize, seq: &[Kumireal]) -> FieldRealtime {
+        debug_assert!(!seq.is_empty());
+
         FieldRealtime {
             player_id: player_id,
+            field: RealPlainField::new(),
             kumipuyo_seq: seq.to_vec(),
+            seq_index: 0,
+            state: SimulationState::Preparing,
+            sleep_for: frame::FRAMES_PREPARING_NEXT,
+            moving: KumipuyoMovingState::initial(),
+            frames_playable: 0,
+            wnext_visible: false,
+            current_chain: 0,
+            max_chain: 0,
+            score: 0,
+            score_carry: 0,
+            num_pending_ojama: 0,
+            num_fixed_ojama: 0,
+            ojama_column_index: 0,
+            zenkeshi: false,
+            user_event: UserEvent::new(),
+        }
+    }
+
+    pub fn player_id(&self) -> usize {
+        self.player_id
+    }
+
+    pub fn field(&self) -> &RealPlainField {
+        &self.field
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    /// Returns the longest chain this player has fired in this game.
+    pub fn max_chain(&self) -> usize {
+        self.max_chain
+    }
+
+    /// Returns the number of ojama which this player will receive.
+    pub fn num_ojama(&self) -> usize {
+        self.num_pending_ojama + self.num_fixed_ojama
+    }
+
+    pub fn num_pending_ojama(&self) -> usize {
+        self.num_pending_ojama
+    }
+
+    pub fn num_fixed_ojama(&self) -> usize {
+        self.num_fixed_ojama
+    }
+
+    /// Receives ojama from the opponent. They won't drop until committed.
+    pub fn add_pending_ojama(&mut self, num: usize) {
+        self.num_pending_ojama += num;
+    }
+
+    /// Fixes the pending ojama. This is called when the opponent's rensa has finished.
+    pub fn commit_ojama(&mut self) {
+        self.num_fixed_ojama += self.num_pending_ojama;
+        self.num_pending_ojama = 0;
+    }
+
+    pub fn state(&self) -> SimulationState {
+        self.state
+    }
+
+    pub fn is_playable(&self) -> bool {
+        self.state == SimulationState::Playable
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.state == SimulationState::Dead
+    }
+
+    /// Returns the events which happened in the last frame.
+    pub fn user_event(&self) -> &UserEvent {
+        &self.user_event
+    }
+
+    /// Returns the n-th kumipuyo from the current one. The sequence is repeated.
+    pub fn kumipuyo(&self, n: usize) -> Kumireal {
+        let len = self.kumipuyo_seq.len();
+        self.kumipuyo_seq[(self.seq_index + n) % len].clone()
+    }
+
+    /// Returns CURRENT, NEXT, and NEXT2 if it has appeared.
+    pub fn visible_seq(&self) -> Vec<Kumireal> {
+        let n = if self.wnext_visible { 3 } else { 2 };
+        (0..n).map(|i| self.kumipuyo(i)).collect()
+    }
+
+    /// Returns the position of the controlled kumipuyo.
+    /// When nothing is controlled, (0, 0, 0) is returned.
+    pub fn kumipuyo_pos(&self) -> KumipuyoPos {
+        if self.is_playable() {
+            self.moving.pos
+        } else {
+            KumipuyoPos::new(0, 0, 0)
+        }
+    }
+
+    /// Returns the state of the controlled kumipuyo. This is meaningful only when playable.
+    pub fn moving_state(&self) -> &KumipuyoMovingState {
+        &self.moving
+    }
+
+    /// Proceeds one frame with `key_set`. `key_set` is ignored unless the state is playable.
+    /// What this player did to the opponent is stored in `context`.
+    pub fn play_one_frame(&mut self, key_set: &KeySet, context: &mut FrameContext) {
+        self.user_event = UserEvent::new();
+
+        match self.state {
+            SimulationState::Preparing => {
+                if self.sleep_for > 0 {
+                    self.sleep_for -= 1;
+                    return;
+                }
+                self.prepare_next();
+            },
+            SimulationState::Playable => {
+                self.play_playable(key_set);
+            },
+            SimulationState::Dropping => {
+                if self.sleep_for > 0 {
+                    self.sleep_for -= 1;
+                    return;
+                }
+                self.state = SimulationState::Grounding;
+                self.sleep_for = frame::FRAMES_GROUNDING;
+            },
+            SimulationState::Grounding => {
+                if self.sleep_for > 0 {
+                    self.sleep_for -= 1;
+                    return;
+                }
+                self.try_vanish(context);
+            },
+            SimulationState::Vanishing => {
+                if self.sleep_for > 0 {
+                    self.sleep_for -= 1;
+                    return;
+                }
+                let max_drops = self.field.drop();
+                if max_drops > 0 {
+                    self.state = SimulationState::Dropping;
+                    self.sleep_for = frame::FRAMES_TO_DROP_FAST[max_drops];
+                } else {
+                    self.state = SimulationState::Grounding;
+                    self.sleep_for = 0;
+                }
+            },
+            SimulationState::OjamaDropping => {
+                if self.sleep_for > 0 {
+                    self.sleep_for -= 1;
+                    return;
+                }
+                self.finish_turn();
+            },
+            SimulationState::Dead => {},
+        }
+    }
+
+    fn prepare_next(&mut self) {
+        if !self.field.is_empty(3, field::HEIGHT) {
+            self.state = SimulationState::Dead;
+            return;
+        }
+
+        self.state = SimulationState::Playable;
+        self.moving = KumipuyoMovingState::initial();
+        self.frames_playable = 0;
+        self.user_event.decision_request = true;
+    }
+
+    fn play_playable(&mut self, key_set: &KeySet) {
+        self.frames_playable += 1;
+        if self.frames_playable == frame::FRAMES_NEXT2_DELAY {
+            self.wnext_visible = true;
+            self.user_event.wnext_appeared = true;
+        }
+
+        self.moving.move_kumipuyo(&self.field, key_set);
+        if !self.moving.grounded {
+            return;
+        }
+
+        // Puts the kumipuyo. A puyo on the 14th row will disappear.
+        let kp = self.kumipuyo(0);
+        let pos = self.moving.pos;
+        if pos.axis_y() <= 13 {
+            self.field.set_color(pos.axis_x() as usize, pos.axis_y() as usize, kp.axis());
+        }
+        if pos.child_y() <= 13 {
+            self.field.set_color(pos.child_x() as usize, pos.child_y() as usize, kp.child());
+        }
+
+        self.user_event.grounded = true;
+        self.user_event.pre_decision_request = true;
+
+        let max_drops = self.field.drop();
+        if max_drops > 0 {
+            // chigiri
+            self.state = SimulationState::Dropping;
+            self.sleep_for = frame::FRAMES_TO_DROP[max_drops];
+        } else {
+            self.state = SimulationState::Grounding;
+            self.sleep_for = frame::FRAMES_GROUNDING;
+        }
+    }
+
+    fn try_vanish(&mut self, context: &mut FrameContext) {
+        let mut score = self.field.vanish(self.current_chain + 1);
+        if score > 0 {
+            if self.zenkeshi {
+                score += score::ZENKESHI_BONUS_SCORE;
+                self.zenkeshi = false;
+            }
+            self.current_chain += 1;
+            self.max_chain = cmp::max(self.max_chain, self.current_chain);
+            self.score += score;
+            self.send_ojama_for_score(score, context);
+            self.user_event.puyo_erased = true;
+            self.state = SimulationState::Vanishing;
+            self.sleep_for = frame::FRAMES_VANISH_ANIMATION;
+            return;
+        }
+
+        self.finish_chain(context);
+    }
+
+    // Converts `score` to ojama. The ojama offset this player's ojama first,
+    // and the rest are sent to the opponent.
+    fn send_ojama_for_score(&mut self, score: usize, context: &mut FrameContext) {
+        let total_score = score + self.score_carry;
+        let mut num_ojama = total_score / score::score_for_ojama(1);
+        self.score_carry = total_score - score::score_for_ojama(num_ojama);
+
+        let offset = cmp::min(num_ojama, self.num_fixed_ojama);
+        self.num_fixed_ojama -= offset;
+        num_ojama -= offset;
+
+        let offset = cmp::min(num_ojama, self.num_pending_ojama);
+        self.num_pending_ojama -= offset;
+        num_ojama -= offset;
+
+        if num_ojama > 0 {
+            context.send_ojama(num_ojama);
+        }
+    }
+
+    fn finish_chain(&mut self, context: &mut FrameContext) {
+        if self.current_chain > 0 {
+            context.commit_ojama();
+            if self.is_all_cleared() {
+                self.zenkeshi = true;
+            }
+        }
+        self.current_chain = 0;
+
+        if self.num_fixed_ojama > 0 {
+            self.sleep_for = self.drop_ojama();
+            self.state = SimulationState::OjamaDropping;
+            self.user_event.ojama_dropped = true;
+            // The field has changed since pre_decision_request.
+            self.user_event.decision_request_again = true;
+            return;
+        }
+
+        self.finish_turn();
+    }
+
+    fn finish_turn(&mut self) {
+        self.seq_index += 1;
+        self.wnext_visible = false;
+        self.state = SimulationState::Preparing;
+        self.sleep_for = frame::FRAMES_PREPARING_NEXT;
+    }
+
+    // Drops the fixed ojama up to MAX_OJAMA_DROP.
+    // Returns the number of frames of the dropping animation.
+    fn drop_ojama(&mut self) -> usize {
+        let num_ojama = cmp::min(self.num_fixed_ojama, MAX_OJAMA_DROP);
+        self.num_fixed_ojama -= num_ojama;
+
+        let mut counts = [num_ojama / field::WIDTH; field::WIDTH + 1];
+        counts[0] = 0;
+        for _ in 0..(num_ojama % field::WIDTH) {
+            counts[OJAMA_COLUMN_ORDER[self.ojama_column_index]] += 1;
+            self.ojama_column_index = (self.ojama_column_index + 1) % field::WIDTH;
+        }
+
+        let mut heights = [0; field::MAP_WIDTH];
+        self.field.calculate_height(&mut heights);
+
+        let mut max_drop = 0;
+        for x in 1..(field::WIDTH + 1) {
+            if counts[x] == 0 {
+                continue;
+            }
+            let h = heights[x] as usize;
+            max_drop = cmp::max(max_drop, 13 - h);
+            for y in (h + 1)..cmp::min(h + 1 + counts[x], 14) {
+                self.field.set_color(x, y, RealColor::OJAMA);
+            }
+        }
+
+        frame::FRAMES_TO_DROP[max_drop] + frame::frames_grounding_ojama(num_ojama)
+    }
+
+    fn is_all_cleared(&self) -> bool {
+        // Puyos have already dropped, so it's enough to check the bottom row.
+        for x in 1..(field::WIDTH + 1) {
+            if !self.field.is_empty(x, 1) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FieldRealtime, SimulationState};
+    use frame_context::FrameContext;
+    use puyoai_core::color::RealColor;
+    use puyoai_core::control::{Key, KeySet};
+    use puyoai_core::field::RealPlainField;
+    use puyoai_core::frame;
+    use puyoai_core::kumipuyo::{KumipuyoPos, Kumireal};
+
+    fn play(fr: &mut FieldRealtime, key_set: &KeySet) {
+        let mut context = FrameContext::new();
+        fr.play_one_frame(key_set, &mut context);
+    }
+
+    fn play_until_decision_request(fr: &mut FieldRealtime) {
+        for _ in 0..1000 {
+            play(fr, &KeySet::new());
+            if fr.user_event().decision_request {
+                return;
+            }
+        }
+        panic!("decision request did not come");
+    }
+
+    // Keeps pressing `key_set` until the kumipuyo is grounded.
+    fn play_until_grounded(fr: &mut FieldRealtime, key_set: KeySet) {
+        for _ in 0..1000 {
+            play(fr, &key_set);
+            if fr.user_event().grounded {
+                return;
+            }
+        }
+        panic!("kumipuyo was not grounded");
+    }
+
+    #[test]
+    fn test_initial_state() {
+        let seq = vec![Kumireal::new(RealColor::RED, RealColor::BLUE)];
+        let mut fr = FieldRealtime::new(0, &seq);
+        assert_eq!(SimulationState::Preparing, fr.state());
+        assert_eq!(2, fr.visible_seq().len());
+
+        for _ in 0..frame::FRAMES_PREPARING_NEXT {
+            play(&mut fr, &KeySet::new());
+            assert!(!fr.is_playable());
+        }
+        play(&mut fr, &KeySet::new());
+        assert!(fr.user_event().decision_request);
+        assert!(fr.is_playable());
+        assert_eq!(KumipuyoPos::initial_pos(), fr.kumipuyo_pos());
+
+        for _ in 1..frame::FRAMES_NEXT2_DELAY {
+            play(&mut fr, &KeySet::new());
+            assert!(!fr.user_event().wnext_appeared);
+        }
+        play(&mut fr, &KeySet::new());
+        assert!(fr.user_event().wnext_appeared);
+        assert_eq!(3, fr.visible_seq().len());
+    }
+
+    #[test]
+    fn test_move_and_turn() {
+        let seq = vec![Kumireal::new(RealColor::RED, RealColor::BLUE)];
+        let mut fr = FieldRealtime::new(0, &seq);
+        play_until_decision_request(&mut fr);
+
+        play(&mut fr, &KeySet::from_keys(&[Key::Left, Key::RightTurn]));
+        assert_eq!(KumipuyoPos::new(2, 12, 1), fr.kumipuyo_pos());
+
+        // Arrow and turn are prohibited for a few frames.
+        play(&mut fr, &KeySet::from_keys(&[Key::Left, Key::RightTurn]));
+        assert_eq!(KumipuyoPos::new(2, 12, 1), fr.kumipuyo_pos());
+    }
+
+    #[test]
+    fn test_wall_kick() {
+        let seq = vec![Kumireal::new(RealColor::RED, RealColor::BLUE)];
+        let mut fr = FieldRealtime::new(0, &seq);
+        play_until_decision_request(&mut fr);
+
+        for _ in 0..10 {
+            play(&mut fr, &KeySet::from_key(Key::Right));
+        }
+        assert_eq!(6, fr.kumipuyo_pos().axis_x());
+
+        play(&mut fr, &KeySet::from_key(Key::RightTurn));
+        assert_eq!(5, fr.kumipuyo_pos().axis_x());
+        assert_eq!(1, fr.kumipuyo_pos().rot());
+    }
+
+    #[test]
+    fn test_quick_turn() {
+        let mut fr = FieldRealtime::new(0, &[Kumireal::new(RealColor::RED, RealColor::BLUE)]);
+        fr.field = RealPlainField::from_str(concat!(
+            ".O.O..", // 12
+            ".O.O..",
+            ".O.O..",
+            ".O.O..",
+            ".O.O..", // 8
+            ".O.O..",
+            ".O.O..",
+            ".O.O..",
+            ".O.O..", // 4
+            ".O.O..",
+            ".O.O..",
+            ".O.O.."));
+        play_until_decision_request(&mut fr);
+
+        play(&mut fr, &KeySet::from_key(Key::RightTurn));
+        assert_eq!(0, fr.kumipuyo_pos().rot());
+        for _ in 0..frame::FRAMES_CONTINUOUS_TURN_PROHIBITED {
+            play(&mut fr, &KeySet::new());
+        }
+        play(&mut fr, &KeySet::from_key(Key::RightTurn));
+        assert_eq!(2, fr.kumipuyo_pos().rot());
+        assert_eq!(3, fr.kumipuyo_pos().axis_x());
+    }
+
+    #[test]
+    fn test_drop_and_rensa() {
+        // PURPLE, which appears only in 5 color games, vanishes as the other colors.
+        let seq = vec![
+            Kumireal::new(RealColor::PURPLE, RealColor::PURPLE),
+            Kumireal::new(RealColor::PURPLE, RealColor::PURPLE),
+        ];
+        let mut fr = FieldRealtime::new(0, &seq);
+
+        play_until_decision_request(&mut fr);
+        play_until_grounded(&mut fr, KeySet::from_key(Key::Down));
+        assert_eq!(&RealPlainField::from_str(concat!(
+            "..P...",
+            "..P...")), fr.field());
+
+        play_until_decision_request(&mut fr);
+        play(&mut fr, &KeySet::from_key(Key::Left));
+        play_until_grounded(&mut fr, KeySet::from_key(Key::Down));
+
+        let mut erased = false;
+        for _ in 0..1000 {
+            play(&mut fr, &KeySet::new());
+            if fr.user_event().puyo_erased {
+                erased = true;
+                break;
+            }
+        }
+        assert!(erased);
+        assert_eq!(&RealPlainField::new(), fr.field());
+        assert_eq!(40, fr.score());
+        assert_eq!(1, fr.max_chain());
+    }
+
+    #[test]
+    fn test_chigiri() {
+        let mut fr = FieldRealtime::new(0, &[Kumireal::new(RealColor::RED, RealColor::BLUE)]);
+        fr.field = RealPlainField::from_str(concat!(
+            "...O..",
+            "...O.."));
+        play_until_decision_request(&mut fr);
+
+        play(&mut fr, &KeySet::from_key(Key::RightTurn));
+        play_until_grounded(&mut fr, KeySet::from_key(Key::Down));
+        assert_eq!(SimulationState::Dropping, fr.state());
+        assert_eq!(&RealPlainField::from_str(concat!(
+            "...B..",
+            "...O..",
+            "..RO..")), fr.field());
+    }
+
+    #[test]
+    fn test_send_ojama_for_score() {
+        let mut fr = FieldRealtime::new(0, &[Kumireal::new(RealColor::RED, RealColor::BLUE)]);
+
+        let mut context = FrameContext::new();
+        fr.send_ojama_for_score(100, &mut context);
+        assert_eq!(1, context.num_sent_ojama());
+        fr.send_ojama_for_score(50, &mut context);
+        assert_eq!(2, context.num_sent_ojama());
+        assert_eq!(10, fr.score_carry);
+    }
+
+    #[test]
+    fn test_offset_ojama() {
+        let mut fr = FieldRealtime::new(0, &[Kumireal::new(RealColor::RED, RealColor::BLUE)]);
+        fr.add_pending_ojama(4);
+        fr.commit_ojama();
+        fr.add_pending_ojama(3);
+        assert_eq!(4, fr.num_fixed_ojama());
+        assert_eq!(3, fr.num_pending_ojama());
+
+        let mut context = FrameContext::new();
+        fr.send_ojama_for_score(70 * 5, &mut context);
+        assert_eq!(0, context.num_sent_ojama());
+        assert_eq!(0, fr.num_fixed_ojama());
+        assert_eq!(2, fr.num_pending_ojama());
+
+        fr.send_ojama_for_score(70 * 5, &mut context);
+        assert_eq!(3, context.num_sent_ojama());
+        assert_eq!(0, fr.num_ojama());
+    }
+
+    #[test]
+    fn test_drop_ojama() {
+        let mut fr = FieldRealtime::new(0, &[Kumireal::new(RealColor::RED, RealColor::BLUE)]);
+        fr.add_pending_ojama(8);
+        fr.commit_ojama();
+        fr.drop_ojama();
+        assert_eq!(&RealPlainField::from_str(concat!(
+            "O..O..",
+            "OOOOOO")), fr.field());
+
+        fr.add_pending_ojama(40);
+        fr.commit_ojama();
+        fr.drop_ojama();
+        assert_eq!(10, fr.num_fixed_ojama());
+        assert_eq!(&RealPlainField::from_str(concat!(
+            "O..O..",
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO",
+            "OOOOOO")), fr.field());
+    }
+
+    #[test]
+    fn test_ojama_drops_after_turn() {
+        let mut fr = FieldRealtime::new(0, &[Kumireal::new(RealColor::RED, RealColor::BLUE)]);
+        play_until_decision_request(&mut fr);
+        fr.add_pending_ojama(6);
+        fr.commit_ojama();
+        play_until_grounded(&mut fr, KeySet::from_key(Key::Down));
+
+        for _ in 0..1000 {
+            play(&mut fr, &KeySet::new());
+            if fr.user_event().ojama_dropped {
+                break;
+            }
+        }
+        assert_eq!(SimulationState::OjamaDropping, fr.state());
+        assert_eq!(&RealPlainField::from_str(concat!(
+            "..O...",
+            "..B...",
+            "OOROOO")), fr.field());
+    }
+
+    #[test]
+    fn test_dead() {
+        let seq = vec![Kumireal::new(RealColor::RED, RealColor::BLUE)];
+        let mut fr = FieldRealtime::new(0, &seq);
+
+        for _ in 0..6 {
+            play_until_decision_request(&mut fr);
+            play_until_grounded(&mut fr, KeySet::from_key(Key::Down));
+        }
+
+        for _ in 0..1000 {
+            play(&mut fr, &KeySet::new());
+            if fr.is_dead() {
+                return;
+            }
         }
+        panic!("player should be dead");
     }
 }
@@ -1,13 +1,23 @@
-struct FrameContext {
+/// FrameContext collects what a player did to the opponent in one frame.
+pub struct FrameContext {
     num_sent_ojama: usize,
     ojama_committed: bool,
 }
 
 impl FrameContext {
+    pub fn new() -> FrameContext {
+        FrameContext {
+            num_sent_ojama: 0,
+            ojama_committed: false,
+        }
+    }
+
     pub fn send_ojama(&mut self, num: usize) {
         self.num_sent_ojama += num;
     }
 
+    /// Marks that the rensa has finished. The ojama sent so far will be fixed,
+    /// and they will drop to the opponent.
     pub fn commit_ojama(&mut self) {
         self.ojama_committed = true;
     }
@@ -15,4 +25,27 @@ impl FrameContext {
     pub fn num_sent_ojama(&self) -> usize {
         self.num_sent_ojama
     }
+
+    pub fn is_ojama_committed(&self) -> bool {
+        self.ojama_committed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameContext;
+
+    #[test]
+    fn test_send_and_commit() {
+        let mut context = FrameContext::new();
+        assert_eq!(0, context.num_sent_ojama());
+        assert!(!context.is_ojama_committed());
+
+        context.send_ojama(3);
+        context.send_ojama(4);
+        assert_eq!(7, context.num_sent_ojama());
+
+        context.commit_ojama();
+        assert!(context.is_ojama_committed());
+    }
 }
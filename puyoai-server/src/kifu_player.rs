@@ -0,0 +1,143 @@
+use puyoai_data::{GameResult, Kifu};
+
+use duel;
+use field_realtime::FieldRealtime;
+
+/// KifuPlayer replays a Kifu frame by frame. The fields after `frame_id` frames are
+/// exactly the same as the fields in the game when the frame was played.
+pub struct KifuPlayer<'a> {
+    kifu: &'a Kifu,
+    fields: [FieldRealtime; 2],
+    // The number of the frames played so far.
+    frame_id: usize,
+}
+
+impl<'a> KifuPlayer<'a> {
+    pub fn new(kifu: &'a Kifu) -> KifuPlayer<'a> {
+        KifuPlayer {
+            kifu: kifu,
+            fields: [FieldRealtime::new(0, &kifu.seqs[0]), FieldRealtime::new(1, &kifu.seqs[1])],
+            frame_id: 0,
+        }
+    }
+
+    pub fn frame_id(&self) -> usize {
+        self.frame_id
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.kifu.frames.len()
+    }
+
+    pub fn field(&self, player_id: usize) -> &FieldRealtime {
+        &self.fields[player_id]
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.frame_id >= self.num_frames()
+    }
+
+    /// Plays the next frame. Returns false if all the frames have been played.
+    pub fn step(&mut self) -> bool {
+        if self.is_end() {
+            return false;
+        }
+
+        duel::play_frame(&mut self.fields, &self.kifu.frames[self.frame_id].key_sets);
+        self.frame_id += 1;
+        true
+    }
+
+    /// Moves to the state after `frame_id` frames. Since the game cannot be played
+    /// backward, seeking backward replays the kifu from the start.
+    pub fn seek(&mut self, frame_id: usize) {
+        if frame_id < self.frame_id {
+            *self = KifuPlayer::new(self.kifu);
+        }
+        while self.frame_id < frame_id && self.step() {}
+    }
+
+    /// Returns the result at the current frame. At the end, this is the recorded result,
+    /// which might be decided by something other than the fields, e.g. a connection loss.
+    pub fn game_result(&self) -> GameResult {
+        if self.is_end() {
+            return self.kifu.result;
+        }
+        duel::game_result_of_fields(&self.fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KifuPlayer;
+    use field_realtime::FieldRealtime;
+    use puyoai_core::control::{Key, KeySet};
+    use puyoai_core::field;
+    use puyoai_core::kumipuyo::kumipuyo_seq;
+    use puyoai_data::{GameResult, Kifu, KifuFrame};
+
+    // Player 1 keeps dropping in the 3rd column, and player 2 does nothing.
+    fn make_kifu(num_frames: usize) -> Kifu {
+        let seq = kumipuyo_seq::generate_ac_sequence_with_seed(4, 1);
+        let mut kifu = Kifu::new(4, 1, [seq.clone(), seq]);
+        for _ in 0..num_frames {
+            let mut frame = KifuFrame::new();
+            frame.key_sets[0] = KeySet::from_key(Key::Down);
+            kifu.frames.push(frame);
+        }
+        kifu.result = GameResult::P2Win;
+        kifu
+    }
+
+    fn assert_same_field(expected: &FieldRealtime, actual: &FieldRealtime) {
+        for x in 0..field::MAP_WIDTH {
+            for y in 0..field::MAP_HEIGHT {
+                assert_eq!(expected.field().color(x, y), actual.field().color(x, y));
+            }
+        }
+        assert_eq!(expected.kumipuyo_pos(), actual.kumipuyo_pos());
+        assert_eq!(expected.score(), actual.score());
+        assert_eq!(expected.state(), actual.state());
+    }
+
+    #[test]
+    fn test_step_and_seek() {
+        let kifu = make_kifu(3000);
+        let mut player = KifuPlayer::new(&kifu);
+        let mut expected = KifuPlayer::new(&kifu);
+
+        player.seek(800);
+        assert_eq!(800, player.frame_id());
+        for _ in 0..500 {
+            assert!(expected.step());
+        }
+
+        // Seeking backward replays from the start.
+        player.seek(500);
+        assert_eq!(500, player.frame_id());
+        for i in 0..2 {
+            assert_same_field(expected.field(i), player.field(i));
+        }
+
+        player.seek(10000);
+        assert!(player.is_end());
+        assert!(!player.step());
+        assert_eq!(3000, player.frame_id());
+    }
+
+    #[test]
+    fn test_game_result() {
+        let kifu = make_kifu(1000);
+        let mut player = KifuPlayer::new(&kifu);
+        assert_eq!(GameResult::Playing, player.game_result());
+
+        // Player 2's kumipuyo also fall to the 3rd column, but much slower.
+        player.seek(999);
+        assert!(player.field(0).is_dead());
+        assert!(!player.field(1).is_dead());
+        assert_eq!(GameResult::P2Win, player.game_result());
+
+        player.seek(1000);
+        assert_eq!(GameResult::P2Win, player.game_result());
+    }
+}
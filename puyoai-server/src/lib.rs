@@ -0,0 +1,13 @@
+//! puyoai-server runs a game between two AI clients.
+//! The game simulation is also used to replay a kifu.
+
+extern crate puyoai_core;
+extern crate puyoai_data;
+
+pub mod connector;
+pub mod duel;
+pub mod field_realtime;
+pub mod frame_context;
+pub mod kifu_player;
+pub mod rating;
+pub mod tournament;
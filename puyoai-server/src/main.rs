@@ -1,7 +1,169 @@
-extern crate puyoai_core;
+extern crate puyoai_server;
+extern crate rand;
 
-mod field_realtime;
-mod frame_context;
+use std::env;
+use std::process;
+
+use puyoai_server::connector::PipeConnector;
+use puyoai_server::duel::{Duel, DuelMode};
+use puyoai_server::tournament::{self, TournamentConfig};
 
 fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() >= 2 && args[1] == "tournament" {
+        run_tournament(&args[0], &args[2..]);
+    } else {
+        run_duel(&args[0], &args[1..]);
+    }
+}
+
+fn run_duel(command: &str, args: &[String]) {
+    let mut num_colors = 4;
+    let mut mode = DuelMode::Realtime;
+    let mut kifu_path = None;
+    let mut programs = Vec::new();
+    for arg in args {
+        if arg.starts_with("--colors=") {
+            num_colors = or_exit(parse_num_colors(&arg["--colors=".len()..]));
+        } else if arg.starts_with("--mode=") {
+            mode = or_exit(parse_mode(&arg["--mode=".len()..]));
+        } else if arg.starts_with("--kifu=") {
+            kifu_path = Some(arg["--kifu=".len()..].to_string());
+        } else {
+            programs.push(arg.clone());
+        }
+    }
+
+    if programs.len() != 2 {
+        println!("Usage: {} [--colors=3|4] [--mode=realtime|lockstep|fastforward] [--kifu=<path>] \
+                  <p1 program> <p2 program>", command);
+        println!("       {} tournament [options] <program>...", command);
+        process::exit(1);
+    }
+
+    let p1 = or_exit(PipeConnector::spawn(&programs[0]));
+    let p2 = or_exit(PipeConnector::spawn(&programs[1]));
+
+    let mut duel = Duel::new(p1, p2, num_colors, rand::random());
+    duel.set_mode(mode);
+    let result = duel.run();
+
+    println!("result: {:?} (frames: {})", result, duel.frame_id());
+
+    // The kifu is written as JSON lines if the path ends with ".jsonl", and as the binary otherwise.
+    if let Some(path) = kifu_path {
+        or_exit(duel.kifu().save(&path));
+    }
+}
+
+// Plays a round robin among the programs, and prints the standings.
+fn run_tournament(command: &str, args: &[String]) {
+    let mut config = TournamentConfig {
+        programs: Vec::new(),
+        num_games: 10,
+        num_threads: 1,
+        num_colors: 4,
+        seed: rand::random(),
+        mode: DuelMode::FastForward,
+        kifu_dir: None,
+    };
+    for arg in args {
+        if arg.starts_with("--games=") {
+            config.num_games = or_exit(parse_positive("--games", &arg["--games=".len()..]));
+        } else if arg.starts_with("--parallel=") {
+            config.num_threads = or_exit(parse_positive("--parallel", &arg["--parallel=".len()..]));
+        } else if arg.starts_with("--colors=") {
+            config.num_colors = or_exit(parse_num_colors(&arg["--colors=".len()..]));
+        } else if arg.starts_with("--mode=") {
+            config.mode = or_exit(parse_mode(&arg["--mode=".len()..]));
+        } else if arg.starts_with("--seed=") {
+            config.seed = or_exit(arg["--seed=".len()..].parse::<usize>()
+                                  .map_err(|_| format!("invalid seed: {}", arg)));
+        } else if arg.starts_with("--kifu-dir=") {
+            config.kifu_dir = Some(arg["--kifu-dir=".len()..].to_string());
+        } else {
+            config.programs.push(arg.clone());
+        }
+    }
+
+    if config.programs.len() < 2 {
+        println!("Usage: {} tournament [--games=N] [--parallel=N] [--colors=3|4] [--seed=N] \
+                  [--mode=realtime|lockstep|fastforward] [--kifu-dir=<dir>] \
+                  <program> <program>...", command);
+        println!("Each pair of programs plays N rounds. A round is 2 games with the same seed and swapped seats.");
+        process::exit(1);
+    }
+
+    println!("seed: {}", config.seed);
+    let records = tournament::run(&config);
+    let standings = tournament::standings(config.programs.len(), &records);
+    tournament::print_standings(&config.programs, &standings);
+}
+
+fn or_exit<T>(result: Result<T, String>) -> T {
+    match result {
+        Ok(v) => v,
+        Err(e) => {
+            println!("{}", e);
+            process::exit(1);
+        },
+    }
+}
+
+// realtime is paced at FPS. lockstep waits for the clients, and fastforward also skips idle frames.
+fn parse_mode(s: &str) -> Result<DuelMode, String> {
+    match s {
+        "realtime" => Ok(DuelMode::Realtime),
+        "lockstep" => Ok(DuelMode::Lockstep),
+        "fastforward" => Ok(DuelMode::FastForward),
+        _ => Err(format!("invalid mode: {}", s)),
+    }
+}
+
+fn parse_positive(key: &str, s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid value for {}: {}", key, s)),
+    }
+}
+
+// Duel can play 5 colors with PURPLE, but puyoai-client maps the colors to the 4 colors of
+// CoreField and exits on the 5th. The programs are all built on it, so 5 colors are rejected.
+fn parse_num_colors(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(5) => Err("5 colors are not supported: puyoai-client handles at most 4 colors".to_string()),
+        Ok(n) if 3 <= n && n <= 4 => Ok(n),
+        _ => Err(format!("invalid number of colors: {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_mode, parse_num_colors, parse_positive};
+    use puyoai_server::duel::DuelMode;
+
+    #[test]
+    fn test_parse_num_colors() {
+        assert_eq!(Ok(3), parse_num_colors("3"));
+        assert_eq!(Ok(4), parse_num_colors("4"));
+        assert!(parse_num_colors("5").unwrap_err().contains("at most 4 colors"));
+        assert!(parse_num_colors("2").is_err());
+        assert!(parse_num_colors("6").is_err());
+        assert!(parse_num_colors("x").is_err());
+    }
+
+    #[test]
+    fn test_parse_positive() {
+        assert_eq!(Ok(8), parse_positive("--games", "8"));
+        assert!(parse_positive("--games", "0").is_err());
+        assert!(parse_positive("--games", "-1").is_err());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(Ok(DuelMode::Realtime), parse_mode("realtime"));
+        assert_eq!(Ok(DuelMode::Lockstep), parse_mode("lockstep"));
+        assert_eq!(Ok(DuelMode::FastForward), parse_mode("fastforward"));
+        assert!(parse_mode("fast").is_err());
+    }
 }
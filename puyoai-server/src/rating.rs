@@ -0,0 +1,212 @@
+//! Ratings of players from game results.
+//!
+//! A game result is `(p1, p2, score)`, where score is the score of p1:
+//! 1.0 for a win, 0.5 for a draw and 0.0 for a loss.
+
+use std::f64::consts::{LN_10, PI};
+
+pub const INITIAL_RATING: f64 = 1500.0;
+pub const INITIAL_DEVIATION: f64 = 350.0;
+
+// The z value of 95% confidence.
+const Z_95: f64 = 1.96;
+
+/// Rating is a rating and its standard deviation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rating {
+    pub rating: f64,
+    pub deviation: f64,
+}
+
+impl Rating {
+    pub fn new(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating: rating,
+            deviation: deviation,
+        }
+    }
+
+    /// Returns the half width of the 95% confidence interval.
+    pub fn confidence_interval(&self) -> f64 {
+        Z_95 * self.deviation
+    }
+}
+
+// The expected score of a player rated `r1` against a player rated `r2`.
+fn expected_score(r1: f64, r2: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((r2 - r1) / 400.0))
+}
+
+/// Returns the Elo ratings which maximize the likelihood of `games` (the Bradley-Terry model).
+/// The average rating is INITIAL_RATING. One virtual draw is added between every two players,
+/// so that a player who wins or loses all the games has a finite rating.
+/// The deviation is computed from the Fisher information, assuming the other ratings are exact.
+pub fn elo_ratings(num_players: usize, games: &[(usize, usize, f64)]) -> Vec<Rating> {
+    // num_games[i][j] is the number of games between i and j, and scores[i] is the total score of i.
+    let mut num_games = vec![vec![0.0; num_players]; num_players];
+    let mut scores = vec![0.0; num_players];
+    for i in 0..num_players {
+        for j in (i + 1)..num_players {
+            num_games[i][j] += 1.0;
+            num_games[j][i] += 1.0;
+            scores[i] += 0.5;
+            scores[j] += 0.5;
+        }
+    }
+    for &(p1, p2, score) in games {
+        num_games[p1][p2] += 1.0;
+        num_games[p2][p1] += 1.0;
+        scores[p1] += score;
+        scores[p2] += 1.0 - score;
+    }
+
+    // Minorization-maximization for the strengths gamma = 10^(rating / 400).
+    let mut gamma = vec![1.0; num_players];
+    for _ in 0..10000 {
+        let mut max_diff: f64 = 0.0;
+        for i in 0..num_players {
+            let mut denom = 0.0;
+            for j in 0..num_players {
+                if i != j {
+                    denom += num_games[i][j] / (gamma[i] + gamma[j]);
+                }
+            }
+            if denom == 0.0 {
+                continue;
+            }
+            let new_gamma = scores[i] / denom;
+            max_diff = max_diff.max((new_gamma / gamma[i]).ln().abs());
+            gamma[i] = new_gamma;
+        }
+        if max_diff < 1e-10 {
+            break;
+        }
+    }
+
+    let mut ratings: Vec<f64> = gamma.iter().map(|g| 400.0 * g.log10()).collect();
+    if num_players > 0 {
+        let offset = INITIAL_RATING - ratings.iter().sum::<f64>() / num_players as f64;
+        for r in ratings.iter_mut() {
+            *r += offset;
+        }
+    }
+
+    // The Fisher information of the rating in the natural unit is sum n p (1 - p).
+    let q = LN_10 / 400.0;
+    let mut result = Vec::with_capacity(num_players);
+    for i in 0..num_players {
+        let mut info = 0.0;
+        for j in 0..num_players {
+            if i != j {
+                let p = expected_score(ratings[i], ratings[j]);
+                info += num_games[i][j] * p * (1.0 - p);
+            }
+        }
+        let deviation = if info > 0.0 { 1.0 / (q * info.sqrt()) } else { INITIAL_DEVIATION };
+        result.push(Rating::new(ratings[i], deviation));
+    }
+    result
+}
+
+fn glicko_g(deviation: f64) -> f64 {
+    let q = LN_10 / 400.0;
+    1.0 / (1.0 + 3.0 * q * q * deviation * deviation / (PI * PI)).sqrt()
+}
+
+/// Updates `player` with the results in one rating period by Glicko.
+/// `results` are the opponents before the period and the scores against them.
+pub fn glicko_update(player: &Rating, results: &[(Rating, f64)]) -> Rating {
+    if results.is_empty() {
+        return *player;
+    }
+
+    let q = LN_10 / 400.0;
+    let mut d_inv = 0.0;
+    let mut sum = 0.0;
+    for &(ref opponent, score) in results {
+        let g = glicko_g(opponent.deviation);
+        let e = 1.0 / (1.0 + 10f64.powf(-g * (player.rating - opponent.rating) / 400.0));
+        d_inv += q * q * g * g * e * (1.0 - e);
+        sum += g * (score - e);
+    }
+
+    let precision = 1.0 / (player.deviation * player.deviation) + d_inv;
+    Rating::new(player.rating + q / precision * sum, (1.0 / precision).sqrt())
+}
+
+/// Returns the Glicko ratings after `periods`. Every player starts from INITIAL_RATING
+/// and INITIAL_DEVIATION, and all the ratings are updated at the end of each period.
+pub fn glicko_ratings(num_players: usize, periods: &[Vec<(usize, usize, f64)>]) -> Vec<Rating> {
+    let mut ratings = vec![Rating::new(INITIAL_RATING, INITIAL_DEVIATION); num_players];
+    for games in periods {
+        let mut results = vec![Vec::new(); num_players];
+        for &(p1, p2, score) in games {
+            results[p1].push((ratings[p2], score));
+            results[p2].push((ratings[p1], 1.0 - score));
+        }
+        ratings = ratings.iter().zip(results.iter()).map(|(r, rs)| glicko_update(r, rs)).collect();
+    }
+    ratings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glicko_update() {
+        // The example in Glickman's paper "The Glicko system".
+        let player = Rating::new(1500.0, 200.0);
+        let results = [
+            (Rating::new(1400.0, 30.0), 1.0),
+            (Rating::new(1550.0, 100.0), 0.0),
+            (Rating::new(1700.0, 300.0), 0.0),
+        ];
+        let updated = glicko_update(&player, &results);
+        assert!((updated.rating - 1464.1).abs() < 0.1, "rating={}", updated.rating);
+        assert!((updated.deviation - 151.4).abs() < 0.1, "deviation={}", updated.deviation);
+    }
+
+    #[test]
+    fn test_glicko_ratings() {
+        let periods = vec![vec![(0, 1, 1.0), (1, 0, 0.0)], vec![(0, 1, 1.0), (1, 0, 0.5)]];
+        let ratings = glicko_ratings(3, &periods);
+        assert!(ratings[0].rating > INITIAL_RATING);
+        assert!(ratings[1].rating < INITIAL_RATING);
+        assert!(ratings[0].deviation < INITIAL_DEVIATION);
+        // The player who has never played stays the same.
+        assert_eq!(Rating::new(INITIAL_RATING, INITIAL_DEVIATION), ratings[2]);
+    }
+
+    #[test]
+    fn test_elo_ratings() {
+        let mut games = Vec::new();
+        for _ in 0..30 {
+            games.push((0, 1, 1.0));
+            games.push((1, 0, 0.0));
+            games.push((0, 1, 0.0));
+            games.push((1, 2, 1.0));
+            games.push((0, 2, 1.0));
+        }
+        let ratings = elo_ratings(3, &games);
+        assert!(ratings[0].rating > ratings[1].rating);
+        assert!(ratings[1].rating > ratings[2].rating);
+        let average = ratings.iter().map(|r| r.rating).sum::<f64>() / 3.0;
+        assert!((average - INITIAL_RATING).abs() < 1e-6);
+
+        // 0 wins 2/3 against 1, which is about 120 Elo.
+        let diff = ratings[0].rating - ratings[1].rating;
+        assert!(100.0 < diff && diff < 140.0, "diff={}", diff);
+
+        // More games make the interval narrower.
+        let fewer = elo_ratings(3, &games[..15]);
+        assert!(fewer[0].confidence_interval() > ratings[0].confidence_interval());
+    }
+
+    #[test]
+    fn test_elo_ratings_all_wins() {
+        let ratings = elo_ratings(2, &[(0, 1, 1.0), (1, 0, 0.0)]);
+        assert!(ratings[0].rating.is_finite());
+        assert!(ratings[0].rating > ratings[1].rating);
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc;
+use std::thread;
+
+use puyoai_data::GameResult;
+
+use connector::PipeConnector;
+use duel::{Duel, DuelMode};
+use rating::{self, Rating};
+
+/// TournamentConfig is how a tournament is run.
+pub struct TournamentConfig {
+    pub programs: Vec<String>,
+    // The number of seeds for each pair of programs. Each seed is played twice with the seats swapped.
+    pub num_games: usize,
+    pub num_threads: usize,
+    pub num_colors: usize,
+    pub seed: usize,
+    pub mode: DuelMode,
+    // If set, the kifu of each game is saved in this directory.
+    pub kifu_dir: Option<String>,
+}
+
+/// Match is one game of a tournament. players are the indices of the programs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub id: usize,
+    pub players: [usize; 2],
+    pub seed: usize,
+    // Games with the same round are played with the same seed, and make one rating period.
+    pub round: usize,
+}
+
+/// GameRecord is the result of a Match.
+#[derive(Clone, Debug)]
+pub struct GameRecord {
+    pub game: Match,
+    pub result: GameResult,
+    pub frames: usize,
+    pub max_chains: [usize; 2],
+}
+
+impl GameRecord {
+    /// Returns the score of the 1st player: 1 for a win, 0.5 for a draw and 0 for a loss.
+    /// A connection loss is a loss. None is returned if the game has not finished.
+    pub fn score(&self) -> Option<f64> {
+        match self.result {
+            GameResult::P1Win | GameResult::P2ConnectionLost => Some(1.0),
+            GameResult::P2Win | GameResult::P1ConnectionLost => Some(0.0),
+            GameResult::Draw => Some(0.5),
+            GameResult::Playing | GameResult::Terminated => None,
+        }
+    }
+}
+
+/// Standing is the summary of one program in a tournament.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub num_games: usize,
+    pub num_wins: usize,
+    pub num_draws: usize,
+    pub num_losses: usize,
+    pub total_max_chain: usize,
+    pub total_frames: usize,
+    pub elo: Rating,
+    pub glicko: Rating,
+}
+
+impl Standing {
+    /// Returns the win rate, where a draw counts as a half win.
+    pub fn win_rate(&self) -> f64 {
+        if self.num_games == 0 {
+            return 0.0;
+        }
+        (self.num_wins as f64 + 0.5 * self.num_draws as f64) / self.num_games as f64
+    }
+
+    /// Returns the average of the longest chain in a game.
+    pub fn average_max_chain(&self) -> f64 {
+        if self.num_games == 0 {
+            return 0.0;
+        }
+        self.total_max_chain as f64 / self.num_games as f64
+    }
+
+    pub fn average_frames(&self) -> f64 {
+        if self.num_games == 0 {
+            return 0.0;
+        }
+        self.total_frames as f64 / self.num_games as f64
+    }
+}
+
+/// Makes the matches of a round robin. Every pair plays `num_games` rounds.
+/// The seed of a round is `seed + round`, and each seed is played twice with the seats swapped,
+/// so that neither the sequence nor the seat favors a program.
+pub fn make_matches(num_programs: usize, num_games: usize, seed: usize) -> Vec<Match> {
+    let mut matches = Vec::new();
+    for round in 0..num_games {
+        for i in 0..num_programs {
+            for j in (i + 1)..num_programs {
+                for &players in [[i, j], [j, i]].iter() {
+                    matches.push(Match {
+                        id: matches.len(),
+                        players: players,
+                        seed: seed.wrapping_add(round),
+                        round: round,
+                    });
+                }
+            }
+        }
+    }
+    matches
+}
+
+/// Runs all the matches with `config.num_threads` games in parallel.
+/// Games which could not be started are reported to stdout and skipped.
+pub fn run(config: &TournamentConfig) -> Vec<GameRecord> {
+    let matches = make_matches(config.programs.len(), config.num_games, config.seed);
+    let num_matches = matches.len();
+    let queue: Arc<Mutex<VecDeque<Match>>> = Arc::new(Mutex::new(matches.into_iter().collect()));
+    let (sender, receiver) = mpsc::channel();
+
+    let mut handles = Vec::new();
+    for _ in 0..config.num_threads.max(1) {
+        let queue = queue.clone();
+        let sender = sender.clone();
+        let programs = config.programs.clone();
+        let num_colors = config.num_colors;
+        let mode = config.mode;
+        let kifu_dir = config.kifu_dir.clone();
+        handles.push(thread::spawn(move || {
+            loop {
+                let game = match queue.lock().unwrap().pop_front() {
+                    Some(game) => game,
+                    None => break,
+                };
+                let record = play(&programs, num_colors, mode, &kifu_dir, game);
+                if sender.send(record).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+    drop(sender);
+
+    let mut records = Vec::new();
+    for record in receiver {
+        match record {
+            Ok(record) => {
+                println!("game {}/{}: {} vs {}: {:?} (frames: {})",
+                         record.game.id + 1, num_matches,
+                         config.programs[record.game.players[0]],
+                         config.programs[record.game.players[1]],
+                         record.result, record.frames);
+                records.push(record);
+            },
+            Err(e) => println!("{}", e),
+        }
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
+
+    records.sort_by_key(|r| r.game.id);
+    records
+}
+
+fn play(programs: &[String], num_colors: usize, mode: DuelMode, kifu_dir: &Option<String>, game: Match)
+        -> Result<GameRecord, String> {
+    let p1 = try!(PipeConnector::spawn(&programs[game.players[0]]));
+    let p2 = try!(PipeConnector::spawn(&programs[game.players[1]]));
+
+    let mut duel = Duel::new(p1, p2, num_colors, game.seed);
+    duel.set_mode(mode);
+    let result = duel.run();
+
+    // The game counts even if its kifu cannot be saved.
+    if let Some(ref dir) = *kifu_dir {
+        let path = Path::new(dir).join(format!("game-{:05}.kifu", game.id + 1));
+        if let Err(e) = duel.kifu().save(&path) {
+            println!("game {}: failed to save the kifu: {}", game.id + 1, e);
+        }
+    }
+
+    Ok(GameRecord {
+        game: game,
+        result: result,
+        frames: duel.kifu().frames.len(),
+        max_chains: [duel.max_chain(0), duel.max_chain(1)],
+    })
+}
+
+/// Summarizes `records` for each program.
+pub fn standings(num_programs: usize, records: &[GameRecord]) -> Vec<Standing> {
+    let mut standings = vec![Standing {
+        num_games: 0,
+        num_wins: 0,
+        num_draws: 0,
+        num_losses: 0,
+        total_max_chain: 0,
+        total_frames: 0,
+        elo: Rating::new(rating::INITIAL_RATING, rating::INITIAL_DEVIATION),
+        glicko: Rating::new(rating::INITIAL_RATING, rating::INITIAL_DEVIATION),
+    }; num_programs];
+
+    let mut games = Vec::new();
+    let mut periods: Vec<Vec<(usize, usize, f64)>> = Vec::new();
+    for record in records {
+        let score = match record.score() {
+            Some(score) => score,
+            None => continue,
+        };
+
+        for k in 0..2 {
+            let s = &mut standings[record.game.players[k]];
+            let my_score = if k == 0 { score } else { 1.0 - score };
+            s.num_games += 1;
+            if my_score > 0.5 {
+                s.num_wins += 1;
+            } else if my_score < 0.5 {
+                s.num_losses += 1;
+            } else {
+                s.num_draws += 1;
+            }
+            s.total_max_chain += record.max_chains[k];
+            s.total_frames += record.frames;
+        }
+
+        let game = (record.game.players[0], record.game.players[1], score);
+        games.push(game);
+        while periods.len() <= record.game.round {
+            periods.push(Vec::new());
+        }
+        periods[record.game.round].push(game);
+    }
+
+    let elos = rating::elo_ratings(num_programs, &games);
+    let glickos = rating::glicko_ratings(num_programs, &periods);
+    for i in 0..num_programs {
+        standings[i].elo = elos[i];
+        standings[i].glicko = glickos[i];
+    }
+    standings
+}
+
+/// Prints the standings as a table.
+pub fn print_standings(programs: &[String], standings: &[Standing]) {
+    println!("{:<24} {:>6} {:>5} {:>5} {:>5} {:>7} {:>14} {:>14} {:>6} {:>8}",
+             "program", "games", "win", "draw", "loss", "rate", "elo", "glicko", "chain", "frames");
+    for (program, s) in programs.iter().zip(standings.iter()) {
+        println!("{:<24} {:>6} {:>5} {:>5} {:>5} {:>6.1}% {:>7.0} +-{:>5.0} {:>7.0} +-{:>5.0} {:>6.2} {:>8.1}",
+                 program, s.num_games, s.num_wins, s.num_draws, s.num_losses, 100.0 * s.win_rate(),
+                 s.elo.rating, s.elo.confidence_interval(),
+                 s.glicko.rating, s.glicko.confidence_interval(),
+                 s.average_max_chain(), s.average_frames());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use puyoai_data::GameResult;
+
+    fn make_record(game: Match, result: GameResult, max_chains: [usize; 2]) -> GameRecord {
+        GameRecord {
+            game: game,
+            result: result,
+            frames: 1000,
+            max_chains: max_chains,
+        }
+    }
+
+    #[test]
+    fn test_make_matches() {
+        let matches = make_matches(3, 2, 100);
+        // 3 pairs * 2 seats * 2 rounds
+        assert_eq!(12, matches.len());
+        for (i, m) in matches.iter().enumerate() {
+            assert_eq!(i, m.id);
+        }
+
+        // Seats are swapped with the same seed.
+        assert_eq!([0, 1], matches[0].players);
+        assert_eq!([1, 0], matches[1].players);
+        assert_eq!(100, matches[0].seed);
+        assert_eq!(100, matches[1].seed);
+        assert_eq!(101, matches[6].seed);
+        assert_eq!(1, matches[6].round);
+    }
+
+    #[test]
+    fn test_score() {
+        let game = make_matches(2, 1, 0)[0].clone();
+        assert_eq!(Some(1.0), make_record(game.clone(), GameResult::P1Win, [0, 0]).score());
+        assert_eq!(Some(1.0), make_record(game.clone(), GameResult::P2ConnectionLost, [0, 0]).score());
+        assert_eq!(Some(0.5), make_record(game.clone(), GameResult::Draw, [0, 0]).score());
+        assert_eq!(Some(0.0), make_record(game.clone(), GameResult::P2Win, [0, 0]).score());
+        assert_eq!(None, make_record(game, GameResult::Terminated, [0, 0]).score());
+    }
+
+    #[test]
+    fn test_standings() {
+        let matches = make_matches(2, 2, 0);
+        let records = vec![
+            make_record(matches[0].clone(), GameResult::P1Win, [10, 3]),
+            make_record(matches[1].clone(), GameResult::P2Win, [2, 8]),
+            make_record(matches[2].clone(), GameResult::Draw, [5, 5]),
+            make_record(matches[3].clone(), GameResult::Terminated, [0, 0]),
+        ];
+        let standings = standings(2, &records);
+
+        assert_eq!(3, standings[0].num_games);
+        assert_eq!(2, standings[0].num_wins);
+        assert_eq!(1, standings[0].num_draws);
+        assert_eq!(0, standings[0].num_losses);
+        assert!((standings[0].win_rate() - 5.0 / 6.0).abs() < 1e-9);
+        assert!((standings[0].average_max_chain() - 23.0 / 3.0).abs() < 1e-9);
+        assert_eq!(2, standings[1].num_losses);
+        assert!((standings[1].average_frames() - 1000.0).abs() < 1e-9);
+
+        assert!(standings[0].elo.rating > standings[1].elo.rating);
+        assert!(standings[0].glicko.rating > standings[1].glicko.rating);
+    }
+}